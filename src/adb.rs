@@ -0,0 +1,291 @@
+use crate::utils;
+use anyhow::{anyhow, Result};
+use process_stream::{stream, Stream, StreamExt};
+use std::env;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::pin::Pin;
+use std::process::Command;
+use tokio::io::AsyncReadExt;
+
+const DEFAULT_SERVER: &str = "127.0.0.1:5037";
+
+// Client for the adb server's smart socket protocol, see OVERVIEW.TXT and
+// SERVICES.TXT in AOSP's packages/modules/adb. Requests are a 4 digit hex
+// length and the service name, answered by OKAY or FAIL and a hex length message.
+#[derive(Clone, Debug)]
+pub struct AdbClient {
+    // host:port of the adb server
+    pub addr: String,
+    // Device to talk to, None lets the server pick the only attached one
+    pub serial: Option<String>,
+}
+
+impl Default for AdbClient {
+    fn default() -> Self {
+        AdbClient {
+            addr: server_addr(),
+            serial: None,
+        }
+    }
+}
+
+pub type DeviceTracker = Pin<Box<dyn Stream<Item = Result<Vec<String>>> + Send>>;
+
+// Honors the same variables as the adb binary
+fn server_addr() -> String {
+    if let Some(addr) = env::var("ADB_SERVER_SOCKET")
+        .ok()
+        .and_then(|s| s.strip_prefix("tcp:").map(ToString::to_string))
+    {
+        return match addr.contains(':') {
+            true => addr,
+            false => format!("127.0.0.1:{}", addr),
+        };
+    }
+    match (
+        env::var("ANDROID_ADB_SERVER_ADDRESS"),
+        env::var("ANDROID_ADB_SERVER_PORT"),
+    ) {
+        (Err(_), Err(_)) => DEFAULT_SERVER.to_string(),
+        (host, port) => format!(
+            "{}:{}",
+            host.unwrap_or("127.0.0.1".to_string()),
+            port.unwrap_or("5037".to_string())
+        ),
+    }
+}
+
+fn send(stream: &mut TcpStream, service: &str) -> Result<()> {
+    stream.write_all(format!("{:04x}{}", service.len(), service).as_bytes())?;
+    read_status(stream)
+}
+
+fn read_status(stream: &mut TcpStream) -> Result<()> {
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status)?;
+    match &status {
+        b"OKAY" => Ok(()),
+        b"FAIL" => Err(anyhow!("adb: {}", read_hex_string(stream)?)),
+        _ => Err(anyhow!(
+            "adb: unexpected response {:?}",
+            String::from_utf8_lossy(&status)
+        )),
+    }
+}
+
+fn read_hex_string(stream: &mut TcpStream) -> Result<String> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = usize::from_str_radix(std::str::from_utf8(&len)?, 16)?;
+    let mut message = vec![0u8; len];
+    stream.read_exact(&mut message)?;
+    Ok(String::from_utf8_lossy(&message).to_string())
+}
+
+// `host:devices` output, serials of the devices in `device` state, skipping
+// offline or unauthorized ones
+pub fn parse_devices(list: &str) -> Vec<String> {
+    list.lines()
+        .filter_map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [serial, "device", ..] => Some(serial.to_string()),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+// Arguments go through the device's shell as one string
+pub fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c))
+    {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+impl AdbClient {
+    pub fn with_serial(&self, serial: Option<String>) -> Self {
+        AdbClient {
+            serial,
+            ..self.clone()
+        }
+    }
+    // Starts the server with the adb binary when nothing is listening, like adb does
+    fn connect(&self) -> Result<TcpStream> {
+        match TcpStream::connect(&self.addr) {
+            Err(err) if err.kind() == ErrorKind::ConnectionRefused => {
+                let adb = utils::adb().map_err(|_| {
+                    anyhow!(
+                        "no adb server at {} and no adb binary to start one",
+                        self.addr
+                    )
+                })?;
+                Command::new(adb).arg("start-server").status()?;
+                Ok(TcpStream::connect(&self.addr)?)
+            }
+            stream => Ok(stream?),
+        }
+    }
+    fn host(&self, service: &str) -> Result<TcpStream> {
+        let mut stream = self.connect()?;
+        send(&mut stream, service)?;
+        Ok(stream)
+    }
+    // A connection to `service` on the device, e.g. `shell:ps`, open until it exits
+    fn open(&self, service: &str) -> Result<TcpStream> {
+        let transport = match &self.serial {
+            Some(serial) => format!("host:transport:{}", serial),
+            None => "host:transport-any".to_string(),
+        };
+        let mut stream = self.host(&transport)?;
+        send(&mut stream, service)?;
+        Ok(stream)
+    }
+    pub async fn open_async(&self, service: &str) -> Result<tokio::net::TcpStream> {
+        let client = self.clone();
+        let service = service.to_string();
+        let stream = tokio::task::spawn_blocking(move || client.open(&service)).await??;
+        stream.set_nonblocking(true)?;
+        Ok(tokio::net::TcpStream::from_std(stream)?)
+    }
+    // stdout and stderr of a shell command
+    pub async fn shell(&self, command: &str) -> Result<Vec<u8>> {
+        let mut stream = self.open_async(&format!("shell:{}", command)).await?;
+        let mut output = Vec::new();
+        stream.read_to_end(&mut output).await?;
+        Ok(output)
+    }
+    pub async fn devices(&self) -> Result<Vec<String>> {
+        let client = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut stream = client.host("host:devices")?;
+            Ok(parse_devices(&read_hex_string(&mut stream)?))
+        })
+        .await?
+    }
+    pub async fn wait_for_device(&self) -> Result<()> {
+        let service = match &self.serial {
+            Some(serial) => format!("host-serial:{}:wait-for-any-device", serial),
+            None => "host:wait-for-any-device".to_string(),
+        };
+        let client = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut stream = client.host(&service)?;
+            // Answered a second time once the device is there
+            read_status(&mut stream)
+        })
+        .await?
+    }
+    // The attached devices, sent again by the server on every change
+    pub async fn track_devices(&self) -> Result<DeviceTracker> {
+        let client = self.clone();
+        let stream =
+            tokio::task::spawn_blocking(move || client.host("host:track-devices")).await??;
+        stream.set_nonblocking(true)?;
+        let mut stream = tokio::net::TcpStream::from_std(stream)?;
+        Ok(stream! {
+            loop {
+                let mut len = [0u8; 4];
+                if stream.read_exact(&mut len).await.is_err() {
+                    break;
+                }
+                let Some(len) = std::str::from_utf8(&len)
+                    .ok()
+                    .and_then(|len| usize::from_str_radix(len, 16).ok())
+                else {
+                    yield Err(anyhow!("adb: malformed device list"));
+                    break;
+                };
+                let mut list = vec![0u8; len];
+                if let Err(err) = stream.read_exact(&mut list).await {
+                    yield Err(err.into());
+                    break;
+                }
+                yield Ok(parse_devices(&String::from_utf8_lossy(&list)));
+            }
+        }
+        .boxed())
+    }
+}
+
+// Plays the server side of one connection, answering each expected request
+#[cfg(test)]
+fn fake_server(exchanges: Vec<(&'static str, &'static str)>) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        for (request, reply) in exchanges {
+            let mut len = [0u8; 4];
+            stream.read_exact(&mut len).unwrap();
+            let len = usize::from_str_radix(std::str::from_utf8(&len).unwrap(), 16).unwrap();
+            let mut service = vec![0u8; len];
+            stream.read_exact(&mut service).unwrap();
+            assert_eq!(String::from_utf8(service).unwrap(), request);
+            stream.write_all(reply.as_bytes()).unwrap();
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn adb_shell() {
+    let addr = fake_server(vec![
+        ("host:transport:emulator-5554", "OKAY"),
+        ("shell:cat /proc/1904/cmdline", "OKAYsystem_server\0"),
+    ]);
+    let client = AdbClient {
+        addr,
+        serial: Some("emulator-5554".to_string()),
+    };
+    assert_eq!(
+        client.shell("cat /proc/1904/cmdline").await.unwrap(),
+        b"system_server\0"
+    );
+
+    let addr = fake_server(vec![("host:transport-any", "FAIL0014more than one device")]);
+    let client = AdbClient { addr, serial: None };
+    assert_eq!(
+        client.shell("ps").await.unwrap_err().to_string(),
+        "adb: more than one device"
+    );
+}
+
+#[tokio::test]
+async fn adb_devices() {
+    let addr = fake_server(vec![(
+        "host:devices",
+        "OKAY003femulator-5554\tdevice\n0123456789ABCDEF\tunauthorized\nR58M\tdevice\n",
+    )]);
+    let client = AdbClient { addr, serial: None };
+    assert_eq!(
+        client.devices().await.unwrap(),
+        vec!["emulator-5554", "R58M"]
+    );
+    assert_eq!(
+        shell_quote("2024-01-01 12:00:00.000"),
+        "'2024-01-01 12:00:00.000'"
+    );
+    assert_eq!(shell_quote("main"), "main");
+}
+
+#[tokio::test]
+async fn adb_track_devices() {
+    let addr = fake_server(vec![(
+        "host:track-devices",
+        "OKAY00000022emulator-5554\tdevice\nR58M\toffline\n",
+    )]);
+    let client = AdbClient { addr, serial: None };
+    let mut tracker = client.track_devices().await.unwrap();
+    assert_eq!(tracker.next().await.unwrap().unwrap(), Vec::<String>::new());
+    assert_eq!(
+        tracker.next().await.unwrap().unwrap(),
+        vec!["emulator-5554"]
+    );
+    assert!(tracker.next().await.is_none());
+}
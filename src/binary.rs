@@ -0,0 +1,243 @@
+use crate::record::{Level, LogBuffer, LogcatRecord};
+use chrono::prelude::*;
+
+// `logcat -B` writes raw `logger_entry` structs:
+//   v1: u16 len, u16 pad, i32 pid, i32 tid, i32 sec, i32 nsec                     (20 bytes)
+//   v2: u16 len, u16 hdr_size, i32 pid, i32 tid, i32 sec, i32 nsec, u32 euid      (24 bytes)
+//   v3: u16 len, u16 hdr_size, i32 pid, i32 tid, i32 sec, i32 nsec, u32 lid       (24 bytes)
+//   v4: u16 len, u16 hdr_size, i32 pid, u32 tid, u32 sec, u32 nsec, u32 lid, u32 uid (28 bytes)
+// followed by `len` bytes of payload.
+pub const V1_HEADER_SIZE: usize = 20;
+pub const HEADER_SIZES: [usize; 3] = [20, 24, 28];
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+fn buffer_from_id(lid: u32) -> Option<LogBuffer> {
+    match lid {
+        0 => Some(LogBuffer::Main),
+        1 => Some(LogBuffer::Radio),
+        2 => Some(LogBuffer::Events),
+        3 => Some(LogBuffer::System),
+        4 => Some(LogBuffer::Crash),
+        5 => Some(LogBuffer::Stats),
+        6 => Some(LogBuffer::Security),
+        7 => Some(LogBuffer::Kernel),
+        _ => None,
+    }
+}
+
+fn level_from_priority(priority: u8) -> Level {
+    match priority {
+        2 => Level::Verbose,
+        3 => Level::Debug,
+        4 => Level::Info,
+        5 => Level::Warn,
+        6 => Level::Error,
+        7 => Level::Fatal,
+        _ => Level::None,
+    }
+}
+
+// Entry sizes, known once the first 4 bytes are read
+pub fn entry_size(header: &[u8]) -> (usize, usize) {
+    let len = u16_at(header, 0) as usize;
+    let hdr_size = match u16_at(header, 2) as usize {
+        0 => V1_HEADER_SIZE,
+        size => size,
+    };
+    (hdr_size, len)
+}
+
+// One complete entry, header and payload
+pub fn parse_entry(entry: &[u8]) -> Option<LogcatRecord> {
+    let (hdr_size, len) = entry_size(entry);
+    if !HEADER_SIZES.contains(&hdr_size) || entry.len() < hdr_size + len {
+        return None;
+    }
+    let pid = u32_at(entry, 4);
+    let tid = u32_at(entry, 8);
+    let sec = u32_at(entry, 12);
+    let nsec = u32_at(entry, 16);
+    let (lid, uid) = match hdr_size {
+        V1_HEADER_SIZE => (None, None),
+        // v2 has the euid where v3 has the log id, ids are tiny
+        24 => match u32_at(entry, 20) {
+            lid @ 0..=7 => (Some(lid), None),
+            euid => (None, Some(euid)),
+        },
+        28 => (Some(u32_at(entry, 20)), Some(u32_at(entry, 24))),
+        _ => return None,
+    };
+    let buffer = lid.and_then(buffer_from_id);
+    let payload = &entry[hdr_size..hdr_size + len];
+    let record = LogcatRecord {
+        timestamp: Local.timestamp_opt(sec as i64, nsec).single(),
+        pid,
+        tid,
+        uid,
+        buffer,
+        ..LogcatRecord::default()
+    };
+    match buffer {
+        Some(LogBuffer::Events | LogBuffer::Stats | LogBuffer::Security) => {
+            parse_event_payload(payload, record)
+        }
+        _ => parse_text_payload(payload, record),
+    }
+}
+
+// u8 priority, NUL terminated tag, NUL terminated message
+fn parse_text_payload(payload: &[u8], record: LogcatRecord) -> Option<LogcatRecord> {
+    let (&priority, rest) = payload.split_first()?;
+    let mut parts = rest.splitn(2, |b| *b == 0);
+    let tag = String::from_utf8_lossy(parts.next()?).to_string();
+    let message = parts.next().unwrap_or_default();
+    let message = message.split(|b| *b == 0).next().unwrap_or_default();
+    let message = String::from_utf8_lossy(message)
+        .trim_end_matches('\n')
+        .to_string();
+    Some(LogcatRecord {
+        level: level_from_priority(priority),
+        tag,
+        message,
+        ..record
+    })
+}
+
+// u32 tag number followed by one typed value, usually a list. The tag stays
+// numeric until `EventTags` names it, the values are printed like logcat does.
+fn parse_event_payload(payload: &[u8], record: LogcatRecord) -> Option<LogcatRecord> {
+    if payload.len() < 4 {
+        return None;
+    }
+    let tag = u32_at(payload, 0);
+    let mut message = String::new();
+    let mut rest = &payload[4..];
+    while !rest.is_empty() {
+        if !message.is_empty() {
+            message.push(',');
+        }
+        rest = format_event_value(rest, &mut message)?;
+    }
+    Some(LogcatRecord {
+        level: Level::Info,
+        tag: tag.to_string(),
+        message,
+        ..record
+    })
+}
+
+fn format_event_value<'a>(buf: &'a [u8], out: &mut String) -> Option<&'a [u8]> {
+    let (&kind, rest) = buf.split_first()?;
+    match kind {
+        // int
+        0 => {
+            out.push_str(&i32::from_le_bytes(rest.get(..4)?.try_into().ok()?).to_string());
+            Some(&rest[4..])
+        }
+        // long
+        1 => {
+            out.push_str(&i64::from_le_bytes(rest.get(..8)?.try_into().ok()?).to_string());
+            Some(&rest[8..])
+        }
+        // string
+        2 => {
+            let len = u32_at(rest.get(..4)?, 0) as usize;
+            out.push_str(&String::from_utf8_lossy(rest.get(4..4 + len)?));
+            Some(&rest[4 + len..])
+        }
+        // list
+        3 => {
+            let (&count, mut rest) = rest.split_first()?;
+            out.push('[');
+            for i in 0..count {
+                if i > 0 {
+                    out.push(',');
+                }
+                rest = format_event_value(rest, out)?;
+            }
+            out.push(']');
+            Some(rest)
+        }
+        // float
+        4 => {
+            out.push_str(&f32::from_le_bytes(rest.get(..4)?.try_into().ok()?).to_string());
+            Some(&rest[4..])
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+fn parse_fixture(bytes: &[u8]) -> Vec<LogcatRecord> {
+    let mut records = Vec::new();
+    let mut rest = bytes;
+    while rest.len() >= 4 {
+        let (hdr_size, len) = entry_size(rest);
+        records.extend(parse_entry(&rest[..hdr_size + len]));
+        rest = &rest[hdr_size + len..];
+    }
+    records
+}
+
+#[test]
+fn parse_binary_v4() {
+    let records = parse_fixture(include_bytes!("../fixtures/logcat-v4.bin"));
+    assert_eq!(records.len(), 3);
+    let res = &records[0];
+    assert_eq!(res.timestamp.unwrap().timestamp(), 1535652653);
+    assert_eq!(res.timestamp.unwrap().nanosecond(), 566_123_456);
+    assert_eq!(res.pid, 1904);
+    assert_eq!(res.tid, 6916);
+    assert_eq!(res.uid, Some(1000));
+    assert_eq!(res.buffer, Some(LogBuffer::Main));
+    assert_eq!(res.level, Level::Debug);
+    assert_eq!(res.tag, "NetworkMonitor/139");
+    assert_eq!(res.message, "PROBE_DNS connect.rom.miui.com 27ms OK");
+
+    let res = &records[1];
+    assert_eq!(res.buffer, Some(LogBuffer::Crash));
+    assert_eq!(res.level, Level::Error);
+    assert_eq!(
+        res.message,
+        "FATAL EXCEPTION: main\njava.lang.NullPointerException"
+    );
+
+    let res = &records[2];
+    assert_eq!(res.buffer, Some(LogBuffer::Events));
+    assert_eq!(res.tag, "30014");
+    assert_eq!(
+        res.message,
+        "[0,24103,10153,com.example.app,activity,{com.example.app/.Main}]"
+    );
+}
+
+#[test]
+fn parse_malformed_header() {
+    // hdr_size 21 with an empty payload, the v4 fields would be read past the end
+    let mut entry = vec![0, 0, 21, 0];
+    entry.extend([0; 17]);
+    assert_eq!(parse_entry(&entry), None);
+    let mut entry = vec![0, 0, 26, 0];
+    entry.extend([0; 22]);
+    assert_eq!(parse_entry(&entry), None);
+}
+
+#[test]
+fn parse_binary_v1() {
+    let records = parse_fixture(include_bytes!("../fixtures/logcat-v1.bin"));
+    assert_eq!(records.len(), 1);
+    let res = &records[0];
+    assert_eq!(res.pid, 1904);
+    assert_eq!(res.tid, 6916);
+    assert_eq!(res.uid, None);
+    assert_eq!(res.buffer, None);
+    assert_eq!(res.level, Level::Info);
+    assert_eq!(res.tag, "ActivityManager");
+    assert_eq!(res.message, "Start proc 24103:com.example.app/u0a153");
+}
@@ -0,0 +1,179 @@
+use crate::record::LogBuffer;
+use crate::theme::{Theme, ThemeSettings};
+use crate::Args;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// Defaults for `Args`, both at the top of the config file and in its profiles
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub hide_timestamp: Option<bool>,
+    pub hide_date: Option<bool>,
+    pub use_process_name: Option<bool>,
+    pub bright_colors: Option<bool>,
+    pub show_buffer: Option<bool>,
+    pub show_thread: Option<bool>,
+    pub process_name_width: Option<usize>,
+    pub tag_width: Option<usize>,
+    pub pid_width: Option<usize>,
+    pub thread_width: Option<usize>,
+    pub filter: Option<Vec<String>>,
+    pub package: Option<Vec<String>>,
+    pub expr: Option<String>,
+    pub buffer: Option<Vec<LogBuffer>>,
+    // Built-in or `[themes.<name>]` theme
+    pub theme: Option<String>,
+}
+
+// ~/.config/r1gcat/config.toml
+//
+//   tag_width = 24
+//   hide_date = false
+//
+//   [profile.camera]
+//   filter = ["Camera*:V", "*:S"]
+//   show_thread = true
+//
+//   [themes.camera]
+//   base = "dark"
+//   tags = { "Camera*" = "cyan" }
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub defaults: Settings,
+    pub profiles: BTreeMap<String, Settings>,
+    pub themes: BTreeMap<String, ThemeSettings>,
+}
+
+impl Settings {
+    // Only fills what wasn't given on the command line
+    pub fn apply(&self, args: &mut Args) {
+        fn fill<T: Clone>(arg: &mut Option<T>, setting: &Option<T>) {
+            if arg.is_none() {
+                *arg = setting.clone();
+            }
+        }
+        fn fill_vec<T: Clone>(arg: &mut Vec<T>, setting: &Option<Vec<T>>) {
+            if let Some(setting) = setting.as_ref().filter(|_| arg.is_empty()) {
+                *arg = setting.clone();
+            }
+        }
+        fill(&mut args.hide_timestamp, &self.hide_timestamp);
+        fill(&mut args.hide_date, &self.hide_date);
+        fill(&mut args.use_process_name, &self.use_process_name);
+        fill(&mut args.bright_colors, &self.bright_colors);
+        fill(&mut args.show_buffer, &self.show_buffer);
+        fill(&mut args.show_thread, &self.show_thread);
+        fill(&mut args.process_name_width, &self.process_name_width);
+        fill(&mut args.tag_width, &self.tag_width);
+        fill(&mut args.pid_width, &self.pid_width);
+        fill(&mut args.thread_width, &self.thread_width);
+        fill(&mut args.theme, &self.theme);
+        fill(&mut args.expr, &self.expr);
+        fill_vec(&mut args.filter, &self.filter);
+        fill_vec(&mut args.package, &self.package);
+        fill_vec(&mut args.buffer, &self.buffer);
+    }
+}
+
+impl Config {
+    // $XDG_CONFIG_HOME/r1gcat/config.toml, or the platform's equivalent
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("r1gcat").join("config.toml"))
+    }
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(content)?;
+        let profiles = match table.remove("profile") {
+            Some(profiles) => profiles.try_into()?,
+            None => BTreeMap::new(),
+        };
+        let themes = match table.remove("themes") {
+            Some(themes) => themes.try_into()?,
+            None => BTreeMap::new(),
+        };
+        Ok(Config {
+            defaults: table.try_into()?,
+            profiles,
+            themes,
+        })
+    }
+    // A missing file is an empty config unless it was asked for explicitly
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, explicit) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                Self::parse(&content).with_context(|| format!("invalid config {}", path.display()))
+            }
+            Err(err) if !explicit && err.kind() == std::io::ErrorKind::NotFound => {
+                Ok(Config::default())
+            }
+            Err(err) => Err(err).with_context(|| format!("can't read config {}", path.display())),
+        }
+    }
+    // Command line first, then the profile, then the config's own defaults
+    pub fn apply(&self, args: &mut Args) -> Result<()> {
+        if let Some(name) = args.profile.clone() {
+            let profile = self.profiles.get(&name).ok_or_else(|| {
+                anyhow!(
+                    "unknown profile '{}', the config has: {}",
+                    name,
+                    self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                )
+            })?;
+            profile.apply(args);
+        }
+        self.defaults.apply(args);
+        Ok(())
+    }
+    pub fn theme(&self, name: Option<&str>) -> Result<Theme> {
+        Theme::named(name.unwrap_or("dark"), &self.themes)
+    }
+}
+
+#[test]
+fn apply_config_profile() {
+    use clap::Parser;
+    let config = Config::parse(
+        r#"
+        tag_width = 24
+        hide_date = false
+        filter = ["*:I"]
+
+        [profile.camera]
+        filter = ["Camera*:V", "*:S"]
+        show_thread = true
+        tag_width = 16
+
+        [profile.network]
+        package = ["com.android.networkstack"]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.profiles.len(), 2);
+
+    let mut args = Args::parse_from(["r1gcat", "--profile", "camera", "--tag-width", "40"]);
+    config.apply(&mut args).unwrap();
+    assert_eq!(args.filter, vec!["Camera*:V", "*:S"]);
+    assert_eq!(args.show_thread, Some(true));
+    assert_eq!(args.tag_width, Some(40));
+    assert_eq!(args.hide_date, Some(false));
+
+    let mut args = Args::parse_from(["r1gcat", "--hide-date", "-f", "MyApp:D"]);
+    config.apply(&mut args).unwrap();
+    assert_eq!(args.filter, vec!["MyApp:D"]);
+    assert_eq!(args.hide_date, Some(true));
+    assert_eq!(args.tag_width, Some(24));
+    assert_eq!(args.show_thread, None);
+
+    let mut args = Args::parse_from(["r1gcat", "--profile", "audio"]);
+    assert!(config.apply(&mut args).is_err());
+    assert!(Config::parse("tag_widht = 3").is_err());
+}
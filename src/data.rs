@@ -1,89 +1,795 @@
-use crate::parser::PSParser;
-use crate::record::ProcessRecord;
-use std::collections::HashMap;
-use std::process::Command;
-use std::sync::{Arc, RwLock};
+use crate::adb::{self, AdbClient};
+use crate::parser::{PSParser, PS_COLUMNS};
+use crate::record::{ProcessEvent, ProcessRecord};
+use chrono::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Notify;
+
+// Processes that lived under the same PID, oldest first
+const INCARNATIONS: usize = 4;
+// Dead processes are kept this long for lines still arriving about them
+const KEEP_DEAD: i64 = 600;
+// `ps` polls between refreshes of the UID to package map
+const PACKAGES_EVERY: usize = 30;
+// `ps -o ETIME` has second precision
+const START_SLACK: i64 = 2;
+// App IDs of app zygote children and isolated services, never listed by `pm`
+const ISOLATED_APP_IDS: std::ops::RangeInclusive<u32> = 90_000..=99_999;
+
 #[derive(Clone, Debug)]
 pub struct ProcessRecords {
-    pub records: Arc<RwLock<HashMap<u32, ProcessRecord>>>,
+    pub records: Arc<RwLock<HashMap<u32, Vec<ProcessRecord>>>>,
     pub enabled: bool,
-    pub adb_cmd: String,
+    // Whether a device is attached to query, false when replaying saved logs
+    pub live: bool,
+    pub client: AdbClient,
+    // Packages followed with `--package`, empty means every process is shown
+    pub packages: Vec<String>,
+    // PIDs of the followed packages and when they were added
+    pub package_pids: Arc<RwLock<HashMap<u32, Instant>>>,
+    // PIDs seen in the log but not in the records, looked up together
+    pub unresolved: Arc<Mutex<HashSet<u32>>>,
+    pub resolve: Arc<Notify>,
+    // App ID to the packages sharing it, from `pm list packages -U`
+    pub uid_packages: Arc<RwLock<HashMap<u32, Vec<String>>>>,
+    // Receives a `ProcessEvent` whenever a poll finds processes started or gone
+    pub events: Option<UnboundedSender<ProcessEvent>>,
+    // How far the device's clock is ahead of the host's, start and death times
+    // are compared with log timestamps so they're kept on the device's clock
+    pub skew: chrono::Duration,
+    // Set by `reset`, the next listing may come from a rebooted device
+    pub reconnected: Arc<AtomicBool>,
 }
 impl Default for ProcessRecords {
     fn default() -> Self {
         ProcessRecords {
             records: Arc::new(RwLock::new(HashMap::new())),
             enabled: false,
-            adb_cmd: "adb".to_string(),
+            live: true,
+            client: AdbClient::default(),
+            packages: Vec::new(),
+            package_pids: Arc::new(RwLock::new(HashMap::new())),
+            unresolved: Arc::new(Mutex::new(HashSet::new())),
+            resolve: Arc::new(Notify::new()),
+            uid_packages: Arc::new(RwLock::new(HashMap::new())),
+            events: None,
+            skew: chrono::Duration::zero(),
+            reconnected: Arc::new(AtomicBool::new(false)),
         }
     }
 }
 
 impl ProcessRecords {
-    pub(crate) fn get_process_record_map(&self, pid: u32) -> Option<ProcessRecord> {
+    pub fn device_now(&self) -> DateTime<Local> {
+        Local::now() + self.skew
+    }
+    // Output of `adb shell <args>`, None if the device couldn't be reached
+    pub async fn shell(&self, args: &[&str]) -> Option<String> {
+        let command = args
+            .iter()
+            .map(|arg| adb::shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let output = self.client.shell(&command).await.ok()?;
+        Some(String::from_utf8_lossy(&output).to_string())
+    }
+    // The process using `pid` at `timestamp`, the latest one without a timestamp
+    pub(crate) fn get_process_record_map(
+        &self,
+        pid: u32,
+        timestamp: Option<DateTime<Local>>,
+    ) -> Option<ProcessRecord> {
         let records = self.records.read().unwrap();
-        records.get(&pid).cloned()
+        let incarnations = records.get(&pid)?;
+        let at = |record: &&ProcessRecord| match (timestamp, record.started) {
+            (Some(timestamp), Some(started)) => started <= timestamp,
+            _ => true,
+        };
+        // Lines older than every known start still belong to the oldest
+        incarnations
+            .iter()
+            .rev()
+            .find(at)
+            .or(incarnations.first())
+            .cloned()
     }
-    pub fn get_process_name(&self, pid: u32) -> String {
+    // Never waits on the device, unknown PIDs are shown as `pid-N` until
+    // `resolve_process_names` has looked them up
+    pub fn get_process_name(&self, pid: u32, timestamp: Option<DateTime<Local>>) -> String {
         if !self.enabled {
             return format!("pid-{}", pid);
         }
-        let mut process_name: Option<String> = self.get_process_record_map(pid).map(|r| r.name);
-        if process_name.is_none() {
-            let cmd = Command::new(&self.adb_cmd)
-                .arg("shell")
-                .arg("cat")
-                .arg(format!("/proc/{}/cmdline", pid))
-                .output();
-            let mut cmdline: String = match cmd {
-                // Get First Part until /0
-                Ok(cmd) => String::from_utf8_lossy(&cmd.stdout)
-                    .split("\0")
-                    .collect::<Vec<&str>>()[0]
-                    .to_string(),
-                Err(_) => format!("pid-{}", pid),
-            };
-            if cmdline.is_empty() {
-                cmdline = format!("pid-{}", pid);
+        if let Some(record) = self.get_process_record_map(pid, timestamp) {
+            return record.name;
+        }
+        if self.live && self.unresolved.lock().unwrap().insert(pid) {
+            self.resolve.notify_one();
+        }
+        format!("pid-{}", pid)
+    }
+    // Reads /proc/<pid>/cmdline for every PID queued since the last round in one shell call
+    pub async fn resolve_process_names(&self) {
+        if !self.live || !self.enabled {
+            return;
+        }
+        loop {
+            self.resolve.notified().await;
+            let pids: Vec<u32> = self.unresolved.lock().unwrap().drain().collect();
+            let pids: Vec<u32> = pids
+                .into_iter()
+                .filter(|pid| !self.records.read().unwrap().contains_key(pid))
+                .collect();
+            if pids.is_empty() {
+                continue;
             }
-            self.records.write().unwrap().insert(
-                pid,
-                ProcessRecord {
-                    name: cmdline.clone(),
+            let script = pids
+                .iter()
+                .map(|pid| {
+                    format!(
+                        "printf '%s ' {0}; cat /proc/{0}/cmdline 2>/dev/null; echo",
+                        pid
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            let Ok(stdout) = self.client.shell(&script).await else {
+                continue;
+            };
+            let names = parse_cmdlines(&String::from_utf8_lossy(&stdout));
+            let mut records = self.records.write().unwrap();
+            for pid in pids {
+                // Exited processes and kernel threads have no cmdline, don't ask again
+                let name = names
+                    .get(&pid)
+                    .cloned()
+                    .unwrap_or_else(|| format!("pid-{}", pid));
+                records.entry(pid).or_insert(vec![ProcessRecord {
+                    name,
                     pid,
+                    added: Some(Instant::now()),
                     ..ProcessRecord::default()
-                },
-            );
-            process_name = Some(cmdline);
-        };
-        process_name.unwrap_or(format!("pid-{}", pid))
+                }]);
+            }
+        }
     }
-    pub async fn update_process_record(&self) {
+    // Only UIDs of a single package name it, shared ones like `system` don't
+    pub fn package_for_uid(&self, uid: u32) -> Option<String> {
+        let uid_packages = self.uid_packages.read().unwrap();
+        match uid_packages.get(&(uid % 100_000))?.as_slice() {
+            [package] => Some(package.clone()),
+            _ => None,
+        }
+    }
+    // The app a process belongs to. Isolated ones are named after the app that
+    // started them: app zygote children run under its parent's UID, isolated
+    // services are forked from the zygote but named like `<package>:sandboxed_process0`.
+    pub fn package_for(
+        &self,
+        pid: u32,
+        uid: Option<u32>,
+        timestamp: Option<DateTime<Local>>,
+    ) -> Option<String> {
+        let uid = uid?;
+        if !ISOLATED_APP_IDS.contains(&(uid % 100_000)) {
+            return self.package_for_uid(uid);
+        }
+        let process = self.get_process_record_map(pid, timestamp)?;
+        let parent = self
+            .get_process_record_map(process.ppid, timestamp)
+            .and_then(|parent| parent.uid)
+            .filter(|uid| !ISOLATED_APP_IDS.contains(&(uid % 100_000)))
+            .and_then(|uid| self.package_for_uid(uid));
+        parent.or_else(|| {
+            let prefix = process.name.split(':').next()?;
+            self.uid_packages
+                .read()
+                .unwrap()
+                .values()
+                .flatten()
+                .find(|package| *package == prefix)
+                .cloned()
+        })
+    }
+    // The UID from the log line, or the one `ps` listed for the process
+    pub fn get_uid(
+        &self,
+        pid: u32,
+        uid: Option<u32>,
+        timestamp: Option<DateTime<Local>>,
+    ) -> Option<u32> {
+        uid.or_else(|| self.get_process_record_map(pid, timestamp)?.uid)
+    }
+    pub fn load_packages_output(&self, stdout: &str) {
+        *self.uid_packages.write().unwrap() = parse_packages(stdout);
+    }
+    // Matches the package itself and its `:remote`-style subprocesses
+    pub fn is_package_process(&self, name: &str) -> bool {
+        self.packages.iter().any(|package| {
+            name == package
+                || name
+                    .strip_prefix(package.as_str())
+                    .is_some_and(|s| s.starts_with(':'))
+        })
+    }
+    // The device may have rebooted while it was away and reused PIDs. The
+    // next listing closes the processes that are gone or changed, a PID back
+    // under the same name is only the same process if it started at the same time.
+    pub fn reset(&self) {
+        self.reconnected.store(true, Ordering::Relaxed);
+    }
+    pub fn is_followed(&self, pid: u32) -> bool {
+        self.packages.is_empty() || self.package_pids.read().unwrap().contains_key(&pid)
+    }
+    fn follow(&self, event: &ProcessEvent, at: Instant) {
+        match event {
+            ProcessEvent::Started { pid, name } if self.is_package_process(name) => {
+                self.package_pids.write().unwrap().insert(*pid, at);
+            }
+            ProcessEvent::Started { pid, .. } | ProcessEvent::Died { pid, .. } => {
+                self.package_pids.write().unwrap().remove(pid);
+            }
+        }
+    }
+    // ActivityManager reports new and dead processes before the next `ps` poll
+    // sees them, `timestamp` is when it logged that
+    pub fn handle_process_event(&self, event: &ProcessEvent, timestamp: Option<DateTime<Local>>) {
+        let mut records = self.records.write().unwrap();
+        match event {
+            ProcessEvent::Started { pid, name } => {
+                let incarnations = records.entry(*pid).or_default();
+                match incarnations.last_mut() {
+                    // Already listed by a poll
+                    Some(current) if current.died.is_none() && current.name == *name => {
+                        current.started = current.started.or(timestamp);
+                    }
+                    current => {
+                        if let Some(current) = current {
+                            current.died = current.died.or(timestamp);
+                        }
+                        incarnations.push(ProcessRecord {
+                            name: name.clone(),
+                            pid: *pid,
+                            started: timestamp,
+                            added: Some(Instant::now()),
+                            ..ProcessRecord::default()
+                        });
+                        trim(incarnations);
+                    }
+                }
+            }
+            ProcessEvent::Died { pid, .. } => {
+                if let Some(current) = records.get_mut(pid).and_then(|i| i.last_mut()) {
+                    current.died = current.died.or(timestamp).or(Some(self.device_now()));
+                }
+            }
+        }
+        drop(records);
+        self.follow(event, Instant::now());
+    }
+    // Merges a `ps` listing, either polled or from a saved dump, in the toolbox
+    // layout or with the columns named in its header. Returns what changed since
+    // the last one: a PID listed under another name or start time is a new process.
+    pub fn load_ps_output(&self, stdout: &str, polled_at: Instant) -> Vec<ProcessEvent> {
         let parser = PSParser {};
+        let now = self.device_now();
+        let mut lines = stdout.split('\n').map(|line| line.trim_end_matches('\r'));
+        let columns = PSParser::columns(lines.next().unwrap_or_default());
+        let listed: HashMap<u32, ProcessRecord> = lines
+            .filter_map(|line| match &columns {
+                Some(columns) => parser.try_parse_columns(columns, line, now),
+                None => parser.try_parse(line),
+            })
+            .map(|record| (record.pid, record))
+            .collect();
+        let mut events = Vec::new();
+        let reconnected = self.reconnected.swap(false, Ordering::Relaxed);
+        let mut records = self.records.write().unwrap();
+        for (pid, record) in listed.iter() {
+            let incarnations = records.entry(*pid).or_default();
+            let rebooted = |current: &ProcessRecord| {
+                reconnected && !same_start(current.started, record.started)
+            };
+            match incarnations.last_mut() {
+                Some(current)
+                    if current.died.is_none()
+                        && same_process(current, record)
+                        && !rebooted(current) =>
+                {
+                    // Apps are forked from the zygote and renamed
+                    if current.name != record.name {
+                        events.push(ProcessEvent::Started {
+                            pid: *pid,
+                            name: record.name.clone(),
+                        });
+                    }
+                    *current = ProcessRecord {
+                        started: current.started.or(record.started),
+                        added: current.added,
+                        ..record.clone()
+                    };
+                }
+                current => {
+                    if let Some(current) = current.filter(|c| c.died.is_none()) {
+                        current.died = Some(now);
+                        events.push(ProcessEvent::Died {
+                            pid: *pid,
+                            name: current.name.clone(),
+                        });
+                    }
+                    events.push(ProcessEvent::Started {
+                        pid: *pid,
+                        name: record.name.clone(),
+                    });
+                    incarnations.push(ProcessRecord {
+                        added: Some(polled_at),
+                        ..record.clone()
+                    });
+                    trim(incarnations);
+                }
+            }
+        }
+        for (pid, incarnations) in records.iter_mut() {
+            let Some(current) = incarnations.last_mut() else {
+                continue;
+            };
+            // Learned from the log while this poll was running
+            let fresh = current.added.is_some_and(|added| added > polled_at);
+            if current.died.is_none() && !fresh && !listed.contains_key(pid) {
+                current.died = Some(now);
+                events.push(ProcessEvent::Died {
+                    pid: *pid,
+                    name: current.name.clone(),
+                });
+            }
+        }
+        records.retain(|_, incarnations| {
+            incarnations
+                .last()
+                .and_then(|current| current.died)
+                .is_none_or(|died| (now - died).num_seconds() < KEEP_DEAD)
+        });
+        drop(records);
+        for event in &events {
+            self.follow(event, polled_at);
+        }
+        events
+    }
+    pub async fn update_process_record(&self) {
+        // Plain `ps` on Android 8+ only lists the shell's own processes, older
+        // toolbox `ps` doesn't know -A or -o and prints its usual listing instead
+        let mut toolbox = false;
+        // Everything is new to the first poll, there's nothing to report
+        let mut first = true;
+        let mut polls = 0;
         #[allow(clippy::while_immutable_condition)]
-        while self.enabled {
-            let cmd = Command::new(&self.adb_cmd)
-                .arg("shell")
-                .arg("ps")
-                .output()
-                .expect("failed to execute process");
-            let stdout = String::from_utf8_lossy(&cmd.stdout);
-            {
-                let mut records = self.records.write().unwrap();
-                records.clear();
-                stdout
-                    .split("\n")
-                    .collect::<Vec<&str>>()
-                    .iter()
-                    .skip(1)
-                    .for_each(|line| {
-                        let record = parser.try_parse(line);
-                        if let Some(record) = record {
-                            records.insert(record.pid, record);
-                        }
+        while self.live && (self.enabled || !self.packages.is_empty()) {
+            let polled_at = Instant::now();
+            // Installed apps change rarely and `pm` is slow to start
+            if polls % PACKAGES_EVERY == 0 {
+                if let Some(stdout) = self.shell(&["pm", "list", "packages", "-U"]).await {
+                    self.load_packages_output(&stdout);
+                }
+            }
+            polls += 1;
+            let args = match toolbox {
+                true => vec!["ps"],
+                false => vec!["ps", "-A", "-o", PS_COLUMNS],
+            };
+            // Keep the last listing while the device is away
+            if let Some(stdout) = self.shell(&args).await {
+                let header = stdout.lines().next().unwrap_or_default();
+                if !toolbox && !header.is_empty() && PSParser::columns(header).is_none() {
+                    toolbox = true;
+                    continue;
+                }
+                let events = self.load_ps_output(&stdout, polled_at);
+                if let Some(sender) = self.events.as_ref().filter(|_| !first) {
+                    events.into_iter().for_each(|event| {
+                        let _ = sender.send(event);
                     });
+                }
+                first = false;
             }
             tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
         }
     }
 }
+
+// Names of threads, resolved in the background like process names
+#[derive(Clone, Debug)]
+pub struct ThreadNames {
+    pub names: Arc<RwLock<HashMap<(u32, u32), String>>>,
+    pub enabled: bool,
+    pub live: bool,
+    pub client: AdbClient,
+    pub unresolved: Arc<Mutex<HashSet<(u32, u32)>>>,
+    pub resolve: Arc<Notify>,
+}
+impl Default for ThreadNames {
+    fn default() -> Self {
+        ThreadNames {
+            names: Arc::new(RwLock::new(HashMap::new())),
+            enabled: false,
+            live: true,
+            client: AdbClient::default(),
+            unresolved: Arc::new(Mutex::new(HashSet::new())),
+            resolve: Arc::new(Notify::new()),
+        }
+    }
+}
+
+impl ThreadNames {
+    // None until looked up, the main thread's name is the truncated process name
+    pub fn get(&self, pid: u32, tid: u32) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+        if pid == tid {
+            return Some("main".to_string());
+        }
+        if let Some(name) = self.names.read().unwrap().get(&(pid, tid)) {
+            return Some(name.clone());
+        }
+        if self.live && self.unresolved.lock().unwrap().insert((pid, tid)) {
+            self.resolve.notify_one();
+        }
+        None
+    }
+    // Thread IDs are reused along with the process
+    pub fn forget(&self, pid: u32) {
+        self.names.write().unwrap().retain(|(p, _), _| *p != pid);
+    }
+    pub fn clear(&self) {
+        self.names.write().unwrap().clear();
+    }
+    // Reads /proc/<pid>/task/<tid>/comm for every thread queued since the last round
+    pub async fn resolve_thread_names(&self) {
+        if !self.live || !self.enabled {
+            return;
+        }
+        loop {
+            self.resolve.notified().await;
+            let threads: Vec<(u32, u32)> = self.unresolved.lock().unwrap().drain().collect();
+            if threads.is_empty() {
+                continue;
+            }
+            let script = threads
+                .iter()
+                .map(|(pid, tid)| {
+                    format!(
+                        "printf '%s %s ' {0} {1}; cat /proc/{0}/task/{1}/comm 2>/dev/null || echo",
+                        pid, tid
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            let Ok(stdout) = self.client.shell(&script).await else {
+                continue;
+            };
+            let names = parse_comms(&String::from_utf8_lossy(&stdout));
+            let mut cache = self.names.write().unwrap();
+            for thread in threads {
+                // Exited threads keep showing their TID
+                let name = names
+                    .get(&thread)
+                    .cloned()
+                    .unwrap_or_else(|| thread.1.to_string());
+                cache.entry(thread).or_insert(name);
+            }
+        }
+    }
+}
+
+// PIDs aren't reused quickly enough to come back under the same name, another
+// name with the same start time is an app renaming itself after the zygote fork.
+// Start times from the log and from `ps` disagree by the device's clock skew.
+fn same_process(known: &ProcessRecord, listed: &ProcessRecord) -> bool {
+    if known.name == listed.name {
+        return true;
+    }
+    matches!((known.started, listed.started), (Some(_), Some(_)))
+        && same_start(known.started, listed.started)
+}
+
+// Unknown start times don't tell processes apart
+fn same_start(known: Option<DateTime<Local>>, listed: Option<DateTime<Local>>) -> bool {
+    match (known, listed) {
+        (Some(known), Some(listed)) => (known - listed).num_seconds().abs() <= START_SLACK,
+        _ => true,
+    }
+}
+
+fn trim(incarnations: &mut Vec<ProcessRecord>) {
+    if incarnations.len() > INCARNATIONS {
+        incarnations.drain(..incarnations.len() - INCARNATIONS);
+    }
+}
+
+// `<pid> <cmdline>` lines, the cmdline is NUL separated and only its first part is the name
+fn parse_cmdlines(stdout: &str) -> HashMap<u32, String> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let (pid, cmdline) = line.trim_end_matches('\r').split_once(' ')?;
+            let name = cmdline.split('\0').next().unwrap_or_default();
+            if name.is_empty() {
+                return None;
+            }
+            Some((pid.parse().ok()?, name.to_string()))
+        })
+        .collect()
+}
+
+// `package:com.example.app uid:10153`, multi-user devices may list several UIDs
+fn parse_packages(stdout: &str) -> HashMap<u32, Vec<String>> {
+    let mut uid_packages: HashMap<u32, Vec<String>> = HashMap::new();
+    for line in stdout.lines() {
+        let Some((package, uids)) = line
+            .trim()
+            .strip_prefix("package:")
+            .and_then(|line| line.split_once(" uid:"))
+        else {
+            continue;
+        };
+        for uid in uids
+            .split(',')
+            .filter_map(|uid| uid.trim().parse::<u32>().ok())
+        {
+            let packages = uid_packages.entry(uid % 100_000).or_default();
+            if !packages.iter().any(|p| p == package) {
+                packages.push(package.to_string());
+            }
+        }
+    }
+    uid_packages
+}
+
+// `<pid> <tid> <comm>` lines
+fn parse_comms(stdout: &str) -> HashMap<(u32, u32), String> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.trim_end_matches('\r').splitn(3, ' ');
+            let pid = parts.next()?.parse().ok()?;
+            let tid = parts.next()?.parse().ok()?;
+            let name = parts.next().filter(|name| !name.is_empty())?;
+            Some(((pid, tid), name.to_string()))
+        })
+        .collect()
+}
+
+#[test]
+fn parse_batched_cmdlines() {
+    let names = parse_cmdlines(
+        "1904 system_server\0\n24103 com.example.app:remote\0--flag\0\n2 \n31337 \n",
+    );
+    assert_eq!(names.len(), 2);
+    assert_eq!(names[&1904], "system_server");
+    assert_eq!(names[&24103], "com.example.app:remote");
+
+    let names = parse_comms("24103 24110 RenderThread\n24103 24111 binder:24103_2\n24103 24112 \n");
+    assert_eq!(names.len(), 2);
+    assert_eq!(names[&(24103, 24110)], "RenderThread");
+    assert_eq!(names[&(24103, 24111)], "binder:24103_2");
+}
+
+#[test]
+fn ps_reused_pid() {
+    let records = ProcessRecords::default();
+    let header = "  PID  PPID   UID USER            RSS S     ELAPSED NAME\n";
+    let ps = |lines: &str| format!("{}{}", header, lines);
+    let events = records.load_ps_output(
+        &ps(
+            "  1904   772  1000 system      1000 S   01:00:00 system_server\n\
+               24103   772 10153 u0_a153     1000 S      00:10 com.example.app\n",
+        ),
+        Instant::now(),
+    );
+    assert_eq!(events.len(), 2);
+    let before = Local::now();
+
+    // The app died and its PID went to a new process
+    let events = records.load_ps_output(
+        &ps(
+            "  1904   772  1000 system      1000 S   01:00:00 system_server\n\
+               24103   772 10160 u0_a160     1000 S      00:00 com.example.other\n",
+        ),
+        Instant::now(),
+    );
+    assert_eq!(
+        events,
+        vec![
+            ProcessEvent::Died {
+                pid: 24103,
+                name: "com.example.app".to_string()
+            },
+            ProcessEvent::Started {
+                pid: 24103,
+                name: "com.example.other".to_string()
+            },
+        ]
+    );
+    // Lines logged before the new process started keep the old name
+    assert_eq!(
+        records
+            .get_process_record_map(24103, Some(before - chrono::Duration::seconds(5)))
+            .unwrap()
+            .name,
+        "com.example.app"
+    );
+    assert_eq!(
+        records
+            .get_process_record_map(24103, Some(Local::now()))
+            .unwrap()
+            .name,
+        "com.example.other"
+    );
+
+    let events = records.load_ps_output(
+        &ps("  1904   772  1000 system      1000 S   01:00:01 system_server\n"),
+        Instant::now(),
+    );
+    assert_eq!(
+        events,
+        vec![ProcessEvent::Died {
+            pid: 24103,
+            name: "com.example.other".to_string()
+        }]
+    );
+}
+
+#[test]
+fn device_clock_skew() {
+    // The device runs a minute ahead, log timestamps are on its clock
+    let records = ProcessRecords {
+        skew: chrono::Duration::seconds(60),
+        ..ProcessRecords::default()
+    };
+    let header = "  PID  PPID   UID USER            RSS S     ELAPSED NAME\n";
+    records.load_ps_output(
+        &format!(
+            "{}24103   772 10153 u0_a153  1000 S  00:30 com.example.app\n",
+            header
+        ),
+        Instant::now(),
+    );
+    let before = records.device_now() - chrono::Duration::seconds(5);
+    records.load_ps_output(
+        &format!(
+            "{}24103   772 10160 u0_a160  1000 S  00:00 com.example.other\n",
+            header
+        ),
+        Instant::now(),
+    );
+    let name = |at| {
+        records
+            .get_process_record_map(24103, Some(at))
+            .unwrap()
+            .name
+    };
+    assert_eq!(name(before), "com.example.app");
+    assert_eq!(
+        name(records.device_now() + chrono::Duration::seconds(1)),
+        "com.example.other"
+    );
+}
+
+#[test]
+fn reset_on_reconnect() {
+    let records = ProcessRecords {
+        packages: vec!["com.example.app".to_string()],
+        ..ProcessRecords::default()
+    };
+    let header = "  PID  PPID   UID USER            RSS S     ELAPSED NAME\n";
+    let ps = |line: &str| format!("{}{}\n", header, line);
+    let app = "24103   772 10153 u0_a153  1000 S  00:10 com.example.app";
+    records.load_ps_output(&ps(app), Instant::now());
+    assert!(records.is_followed(24103));
+
+    // Replugged, nothing restarted
+    records.reset();
+    assert_eq!(records.load_ps_output(&ps(app), Instant::now()), vec![]);
+    assert!(records.is_followed(24103));
+
+    // After a reboot the PID belongs to something else
+    let reconnected = records.device_now();
+    records.reset();
+    let phone = "24103   772  1000 system   1000 S  00:00 com.android.phone";
+    assert_eq!(
+        records.load_ps_output(&ps(phone), Instant::now()),
+        vec![
+            ProcessEvent::Died {
+                pid: 24103,
+                name: "com.example.app".to_string()
+            },
+            ProcessEvent::Started {
+                pid: 24103,
+                name: "com.android.phone".to_string()
+            },
+        ]
+    );
+    assert!(!records.is_followed(24103));
+    let name = |at| {
+        records
+            .get_process_record_map(24103, Some(at))
+            .unwrap()
+            .name
+    };
+    assert_eq!(
+        name(reconnected - chrono::Duration::seconds(60)),
+        "com.example.app"
+    );
+    assert_eq!(
+        name(records.device_now() + chrono::Duration::seconds(1)),
+        "com.android.phone"
+    );
+
+    // Same name, started at another time
+    records.reset();
+    let restarted = "24103   772  1000 system   1000 S  05:00 com.android.phone";
+    assert_eq!(
+        records.load_ps_output(&ps(restarted), Instant::now()).len(),
+        2
+    );
+}
+
+#[test]
+fn uid_to_package() {
+    let records = ProcessRecords::default();
+    records.load_packages_output(
+        "package:com.example.app uid:10153\r\n\
+         package:com.android.settings uid:1000\n\
+         package:android uid:1000\n\
+         package:com.example.work uid:10160,1010160\n",
+    );
+    assert_eq!(
+        records.package_for_uid(10153).as_deref(),
+        Some("com.example.app")
+    );
+    // Same app for user 10
+    assert_eq!(
+        records.package_for_uid(1010153).as_deref(),
+        Some("com.example.app")
+    );
+    assert_eq!(
+        records.package_for_uid(10160).as_deref(),
+        Some("com.example.work")
+    );
+    assert_eq!(records.package_for_uid(1000), None);
+    assert_eq!(records.package_for_uid(99005), None);
+}
+
+#[test]
+fn isolated_process_package() {
+    let records = ProcessRecords::default();
+    records.load_packages_output(
+        "package:com.example.app uid:10153\n\
+         package:com.android.chrome uid:10120\n",
+    );
+    records.load_ps_output(
+        "  PID  PPID   UID USER      RSS S  ELAPSED NAME\n\
+           772     1     0 root     1000 S 01:00:00 zygote64\n\
+         24103   772 10153 u0_a153  1000 S    00:10 com.example.app_zygote\n\
+         24180 24103 90012 u0_ai12  1000 S    00:05 org.example.Renderer\n\
+         25000   772 99005 u0_i5    1000 S    00:05 com.android.chrome:sandboxed_process0:org.chromium.content.app.SandboxedProcessService0:0\n\
+         26000   772 99006 u0_i6    1000 S    00:05 org.example.Unknown\n",
+        Instant::now(),
+    );
+    let package = |pid| {
+        let uid = records.get_uid(pid, None, None);
+        records.package_for(pid, uid, None)
+    };
+    assert_eq!(records.get_uid(24180, None, None), Some(90012));
+    // Child of the app's zygote
+    assert_eq!(package(24180).as_deref(), Some("com.example.app"));
+    // Isolated service named after its app
+    assert_eq!(package(25000).as_deref(), Some("com.android.chrome"));
+    assert_eq!(package(26000), None);
+    assert_eq!(package(24103).as_deref(), Some("com.example.app"));
+    // Not listed yet
+    assert_eq!(records.package_for(27000, Some(99007), None), None);
+}
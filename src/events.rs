@@ -0,0 +1,196 @@
+use crate::record::{LogBuffer, LogcatRecord};
+use std::collections::HashMap;
+
+// Value types from event-log-tags, see `logtags` in AOSP
+const EVENT_STRING: u8 = 3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventField {
+    pub name: String,
+    pub kind: u8,
+}
+
+// Descriptions of the `events` buffer entries, read from /system/etc/event-log-tags
+#[derive(Clone, Debug, Default)]
+pub struct EventTags {
+    pub tags: HashMap<String, Vec<EventField>>,
+    // Tag numbers, `logcat -B` entries only carry those
+    pub names: HashMap<u32, String>,
+}
+
+impl EventTags {
+    // 30014 am_proc_start (User|1|5),(PID|1|5),(UID|1|5),(Process Name|3),(Type|3),(Component|3)
+    pub fn parse(content: &str) -> Self {
+        let mut names = HashMap::new();
+        let tags = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (number, rest) = line.split_once(char::is_whitespace)?;
+                let number = number.parse::<u32>().ok()?;
+                let rest = rest.trim_start();
+                let (name, description) =
+                    rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                let fields = description
+                    .split("),")
+                    .filter_map(|field| {
+                        let field = field.trim().trim_start_matches('(').trim_end_matches(')');
+                        let mut parts = field.split('|');
+                        let name = parts.next().filter(|n| !n.is_empty())?;
+                        Some(EventField {
+                            name: name.trim().to_lowercase().replace(' ', "_"),
+                            kind: parts.next().and_then(|k| k.parse().ok()).unwrap_or(0),
+                        })
+                    })
+                    .collect();
+                names.insert(number, name.to_string());
+                Some((name.to_string(), fields))
+            })
+            .collect();
+        EventTags { tags, names }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+    // Replaces the numeric tag of a binary event entry with its name
+    pub fn name(&self, record: &mut LogcatRecord) {
+        if !matches!(record.buffer, None | Some(LogBuffer::Events)) {
+            return;
+        }
+        if let Some(name) = record.tag.parse().ok().and_then(|n| self.names.get(&n)) {
+            record.tag = name.clone();
+        }
+    }
+    // logcat prints event payloads as `[0,24103,com.example.app]`, or bare for single values
+    pub fn decode(&self, record: &mut LogcatRecord) {
+        if !matches!(record.buffer, None | Some(LogBuffer::Events)) {
+            return;
+        }
+        let Some(fields) = self.tags.get(&record.tag) else {
+            return;
+        };
+        if fields.is_empty() {
+            return;
+        }
+        let message = record.message.trim();
+        let mut values = match message.strip_prefix('[').and_then(|m| m.strip_suffix(']')) {
+            Some(list) => split_values(list),
+            None => vec![message.to_string()],
+        };
+        // Strings may contain commas, give the surplus back to the only string field
+        if values.len() > fields.len() {
+            let strings: Vec<usize> = (0..fields.len())
+                .filter(|i| fields[*i].kind == EVENT_STRING)
+                .collect();
+            let at = match strings[..] {
+                [at] => at,
+                _ => fields.len() - 1,
+            };
+            let surplus = values.len() - fields.len();
+            let merged = values
+                .drain(at..=at + surplus)
+                .collect::<Vec<_>>()
+                .join(",");
+            values.insert(at, merged);
+        }
+        record.fields = fields.iter().map(|f| f.name.clone()).zip(values).collect();
+    }
+}
+
+// Top level commas only, component names like {com.foo/.Bar} stay intact
+fn split_values(list: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    for c in list.chars() {
+        match c {
+            '[' | '{' | '(' => depth += 1,
+            ']' | '}' | ')' => depth -= 1,
+            ',' if depth == 0 => {
+                values.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    values.push(current);
+    values
+}
+
+#[test]
+fn decode_event_record() {
+    let tags = EventTags::parse(
+        "# comment\n\
+         30014 am_proc_start (User|1|5),(PID|1|5),(UID|1|5),(Process Name|3),(Type|3),(Component|3)\n\
+         2722 battery_level (level|1|6),(voltage|1|1),(temperature|1|1)\n\
+         42 answer (to life the universe etc|3)\n",
+    );
+    let mut record = LogcatRecord {
+        tag: "30014".to_string(),
+        message: "[0,24103,10153,com.example.app,activity,{com.example.app/.Main}]".to_string(),
+        buffer: Some(LogBuffer::Events),
+        ..LogcatRecord::default()
+    };
+    tags.name(&mut record);
+    assert_eq!(record.tag, "am_proc_start");
+    tags.decode(&mut record);
+    assert_eq!(
+        record.fields,
+        vec![
+            ("user".to_string(), "0".to_string()),
+            ("pid".to_string(), "24103".to_string()),
+            ("uid".to_string(), "10153".to_string()),
+            ("process_name".to_string(), "com.example.app".to_string()),
+            ("type".to_string(), "activity".to_string()),
+            (
+                "component".to_string(),
+                "{com.example.app/.Main}".to_string()
+            ),
+        ]
+    );
+
+    let mut record = LogcatRecord {
+        tag: "answer".to_string(),
+        message: "42, really".to_string(),
+        ..LogcatRecord::default()
+    };
+    tags.decode(&mut record);
+    assert_eq!(
+        record.fields,
+        vec![(
+            "to_life_the_universe_etc".to_string(),
+            "42, really".to_string()
+        )]
+    );
+
+    let mut record = LogcatRecord {
+        tag: "battery_level".to_string(),
+        message: "[100,4200,290]".to_string(),
+        buffer: Some(LogBuffer::Main),
+        ..LogcatRecord::default()
+    };
+    tags.decode(&mut record);
+    assert!(record.fields.is_empty());
+}
+
+#[test]
+fn grep_decoded_fields() {
+    use crate::filter::{Grep, GrepOptions};
+    use crate::utils::message_text;
+
+    let tags = EventTags::parse("2722 battery_level (level|1|6),(voltage|1|1),(temperature|1|1)\n");
+    let mut record = LogcatRecord {
+        tag: "2722".to_string(),
+        message: "[15,3600,290]".to_string(),
+        buffer: Some(LogBuffer::Events),
+        ..LogcatRecord::default()
+    };
+    let grep = Grep::new(&["level=15 "], &[], &[], GrepOptions::default()).unwrap();
+    assert!(!grep.matches(&record));
+    tags.name(&mut record);
+    tags.decode(&mut record);
+    assert!(grep.matches(&record));
+    assert_eq!(grep.highlights(&message_text(&record)), vec![(0, 9)]);
+}
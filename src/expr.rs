@@ -0,0 +1,509 @@
+use crate::record::{Level, LogcatRecord};
+use crate::utils::{in_zone, message_text};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone};
+use nom::branch::alt;
+use nom::bytes::complete::{escaped_transform, is_not, tag, take_till1, take_while1};
+use nom::character::complete::{char, multispace0, one_of, satisfy};
+use nom::combinator::{eof, not, value};
+use nom::error::{ErrorKind, ParseError};
+use nom::sequence::{delimited, preceded, terminated};
+use nom::{IResult, Parser};
+use regex::Regex;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+// `--expr` filters, e.g.
+//   level>=W and (tag~Camera or process=com.foo) and not message~heartbeat
+// `and` binds tighter than `or`, `&&`, `||` and `!` work too. Values with
+// spaces or parentheses are quoted, `~` and `!~` take regexes.
+#[derive(Debug)]
+pub struct Expression {
+    root: Node,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Level,
+    Tag,
+    Pid,
+    Tid,
+    Uid,
+    Process,
+    Package,
+    Message,
+    Time,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("level", Field::Level),
+    ("tag", Field::Tag),
+    ("pid", Field::Pid),
+    ("tid", Field::Tid),
+    ("uid", Field::Uid),
+    ("process", Field::Process),
+    ("package", Field::Package),
+    ("message", Field::Message),
+    ("time", Field::Time),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Match,
+    NotMatch,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+enum Value {
+    Level(Level),
+    Number(u32),
+    Text(String),
+    Regex(Regex),
+    // Any day at that time
+    TimeOfDay(NaiveTime),
+    Time(DateTime<Local>),
+}
+
+#[derive(Debug)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Compare(Field, Op, Value),
+}
+
+// Where parsing stopped, with a message for everything but plain syntax errors
+#[derive(Debug)]
+struct ExprError<'a> {
+    input: &'a str,
+    len: usize,
+    message: Option<String>,
+}
+
+impl<'a> ParseError<&'a str> for ExprError<'a> {
+    fn from_error_kind(input: &'a str, _: ErrorKind) -> Self {
+        ExprError {
+            input,
+            len: 0,
+            message: None,
+        }
+    }
+    fn append(_: &'a str, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+    // The alternative that got further explains the failure best
+    fn or(self, other: Self) -> Self {
+        if other.input.len() <= self.input.len() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+type ParseResult<'a, T> = IResult<&'a str, T, ExprError<'a>>;
+
+fn fail<'a, T>(input: &'a str, len: usize, message: String) -> ParseResult<'a, T> {
+    Err(nom::Err::Failure(ExprError {
+        input,
+        len,
+        message: Some(message),
+    }))
+}
+
+// The word an error points at
+fn token(s: &str) -> &str {
+    let end = s
+        .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .unwrap_or(s.len());
+    match end {
+        0 => &s[..s.chars().next().map_or(0, char::len_utf8)],
+        end => &s[..end],
+    }
+}
+
+impl Op {
+    fn compare(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+            Op::Match | Op::NotMatch => false,
+        }
+    }
+    fn is_ordering(self) -> bool {
+        matches!(self, Op::Lt | Op::Le | Op::Gt | Op::Ge)
+    }
+    fn is_match(self) -> bool {
+        matches!(self, Op::Match | Op::NotMatch)
+    }
+}
+
+fn parse_level(text: &str) -> Option<Level> {
+    match Level::from(text) {
+        Level::None => None,
+        level => Some(level),
+    }
+}
+
+fn parse_time_value(text: &str) -> Option<Value> {
+    for format in ["%H:%M:%S%.f", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(text, format) {
+            return Some(Value::TimeOfDay(time));
+        }
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(text, format) {
+            return Local.from_local_datetime(&time).earliest().map(Value::Time);
+        }
+    }
+    None
+}
+
+fn field_name(field: Field) -> &'static str {
+    FIELDS.iter().find(|(_, f)| *f == field).map_or("", |f| f.0)
+}
+
+fn check_op(field: Field, op: Op, op_text: &str) -> Result<(), String> {
+    match field {
+        Field::Level | Field::Pid | Field::Tid | Field::Uid | Field::Time if op.is_match() => Err(
+            format!("'{}' doesn't apply to {}", op_text, field_name(field)),
+        ),
+        Field::Tag | Field::Process | Field::Package | Field::Message if op.is_ordering() => {
+            Err(format!(
+                "'{}' doesn't apply to {}, use = != ~ or !~",
+                op_text,
+                field_name(field)
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Checks the value against the field once instead of for every record
+fn compile(field: Field, op: Op, text: &str) -> Result<Value, String> {
+    match field {
+        Field::Level => parse_level(text).map(Value::Level).ok_or_else(|| {
+            format!(
+                "unknown level '{}', expected one of V D I W E F A or their names",
+                text
+            )
+        }),
+        Field::Pid | Field::Tid | Field::Uid => text
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| format!("'{}' isn't a number", text)),
+        Field::Time => parse_time_value(text).ok_or_else(|| {
+            format!(
+                "'{}' isn't a time, expected HH:MM[:SS[.mmm]] or YYYY-MM-DD HH:MM[:SS]",
+                text
+            )
+        }),
+        _ if op.is_match() => Regex::new(text).map(Value::Regex).map_err(|err| {
+            let err = err.to_string();
+            format!(
+                "invalid regex '{}': {}",
+                text,
+                err.lines().last().unwrap_or_default()
+            )
+        }),
+        _ => Ok(Value::Text(text.to_string())),
+    }
+}
+
+fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> ParseResult<'a, &'a str> {
+    terminated(tag(word), not(satisfy(|c| c.is_alphanumeric() || c == '_')))
+}
+
+// "quoted \" value", 'single quoted' or a bare word
+fn parse_value(s: &str) -> ParseResult<'_, String> {
+    let quoted = |quote: char| {
+        delimited(
+            char(quote),
+            alt((
+                escaped_transform(
+                    is_not(if quote == '"' { "\\\"" } else { "\\'" }),
+                    '\\',
+                    one_of("\\\"'"),
+                ),
+                value(String::new(), tag("")),
+            )),
+            char(quote),
+        )
+    };
+    alt((
+        quoted('"'),
+        quoted('\''),
+        take_till1(|c: char| c.is_whitespace() || c == '(' || c == ')').map(String::from),
+    ))(s)
+}
+
+fn parse_comparison(s: &str) -> ParseResult<'_, Node> {
+    let (rest, name) = take_while1(|c: char| c.is_alphanumeric() || c == '_')(s)?;
+    let Some(&(_, field)) = FIELDS.iter().find(|(f, _)| *f == name) else {
+        return fail(
+            s,
+            name.len(),
+            format!(
+                "unknown field '{}', expected one of {}",
+                name,
+                FIELDS.iter().map(|f| f.0).collect::<Vec<_>>().join(", ")
+            ),
+        );
+    };
+    let (op_start, _) = multispace0(rest)?;
+    let op: ParseResult<Op> = alt((
+        value(Op::Ne, tag("!=")),
+        value(Op::NotMatch, tag("!~")),
+        value(Op::Ge, tag(">=")),
+        value(Op::Le, tag("<=")),
+        value(Op::Eq, tag("==")),
+        value(Op::Eq, tag("=")),
+        value(Op::Match, tag("~")),
+        value(Op::Gt, tag(">")),
+        value(Op::Lt, tag("<")),
+    ))(op_start);
+    let Ok((rest, op)) = op else {
+        return fail(
+            op_start,
+            token(op_start).len(),
+            format!("expected one of = != ~ !~ < <= > >= after '{}'", name),
+        );
+    };
+    let op_text = &op_start[..op_start.len() - rest.len()];
+    if let Err(message) = check_op(field, op, op_text) {
+        return fail(op_start, op_text.len(), message);
+    }
+    let (value_start, _) = multispace0(rest)?;
+    let Ok((rest, text)) = parse_value(value_start) else {
+        return fail(
+            value_start,
+            token(value_start).len(),
+            format!("expected a value after '{}'", op_text),
+        );
+    };
+    match compile(field, op, &text) {
+        Ok(value) => Ok((rest, Node::Compare(field, op, value))),
+        Err(message) => fail(value_start, value_start.len() - rest.len(), message),
+    }
+}
+
+fn parse_atom(s: &str) -> ParseResult<'_, Node> {
+    let (s, _) = multispace0(s)?;
+    if let Ok((rest, _)) = char::<_, ExprError>('(')(s) {
+        let (rest, node) = parse_or(rest)?;
+        let (rest, _) = multispace0(rest)?;
+        return match char::<_, ExprError>(')')(rest) {
+            Ok((rest, _)) => Ok((rest, node)),
+            Err(_) => fail(s, 1, "unclosed '('".to_string()),
+        };
+    }
+    parse_comparison(s)
+}
+
+fn parse_not(s: &str) -> ParseResult<'_, Node> {
+    match preceded(multispace0, alt((keyword("not"), tag("!"))))(s) {
+        Ok((rest, _)) => {
+            let (rest, node) = parse_not(rest)?;
+            Ok((rest, Node::Not(Box::new(node))))
+        }
+        Err(nom::Err::Error(_)) => parse_atom(s),
+        Err(err) => Err(err),
+    }
+}
+
+fn parse_and(s: &str) -> ParseResult<'_, Node> {
+    let (mut s, mut node) = parse_not(s)?;
+    loop {
+        match preceded(multispace0, alt((keyword("and"), tag("&&"))))(s) {
+            Ok((rest, _)) => {
+                let (rest, right) = parse_not(rest)?;
+                node = Node::And(Box::new(node), Box::new(right));
+                s = rest;
+            }
+            Err(nom::Err::Error(_)) => return Ok((s, node)),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn parse_or(s: &str) -> ParseResult<'_, Node> {
+    let (mut s, mut node) = parse_and(s)?;
+    loop {
+        match preceded(multispace0, alt((keyword("or"), tag("||"))))(s) {
+            Ok((rest, _)) => {
+                let (rest, right) = parse_and(rest)?;
+                node = Node::Or(Box::new(node), Box::new(right));
+                s = rest;
+            }
+            Err(nom::Err::Error(_)) => return Ok((s, node)),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn text_field(record: &LogcatRecord, field: Field) -> Cow<'_, str> {
+    match field {
+        Field::Tag => Cow::Borrowed(&record.tag),
+        Field::Process => Cow::Borrowed(&record.process_name),
+        Field::Package => Cow::Borrowed(record.package.as_deref().unwrap_or_default()),
+        _ => Cow::Owned(message_text(record)),
+    }
+}
+
+impl Node {
+    fn matches(&self, record: &LogcatRecord) -> bool {
+        match self {
+            Node::And(left, right) => left.matches(record) && right.matches(record),
+            Node::Or(left, right) => left.matches(record) || right.matches(record),
+            Node::Not(node) => !node.matches(record),
+            Node::Compare(field, op, value) => match value {
+                Value::Level(level) => record
+                    .level
+                    .partial_cmp(level)
+                    .is_some_and(|o| op.compare(o)),
+                Value::Number(n) => match field {
+                    Field::Pid => Some(record.pid),
+                    Field::Tid => Some(record.tid),
+                    _ => record.uid,
+                }
+                .is_some_and(|v| op.compare(v.cmp(n))),
+                Value::Text(text) => op.compare(text_field(record, *field).as_ref().cmp(text)),
+                Value::Regex(regex) => {
+                    regex.is_match(&text_field(record, *field)) == (*op == Op::Match)
+                }
+                // The time of day as printed, `--tz` may differ from the host
+                Value::TimeOfDay(time) => record
+                    .timestamp
+                    .is_some_and(|t| op.compare(in_zone(t).time().cmp(time))),
+                Value::Time(time) => record.timestamp.is_some_and(|t| op.compare(t.cmp(time))),
+            },
+        }
+    }
+}
+
+impl Expression {
+    pub fn parse(expr: &str) -> Result<Self> {
+        match terminated(parse_or, preceded(multispace0, eof))(expr) {
+            Ok((_, root)) => Ok(Expression { root }),
+            Err(nom::Err::Error(err) | nom::Err::Failure(err)) => {
+                let column = expr[..expr.len() - err.input.len()].chars().count();
+                let token = token(err.input);
+                let message = err.message.unwrap_or_else(|| match token {
+                    "" => "unexpected end of expression".to_string(),
+                    token => format!("unexpected '{}'", token),
+                });
+                let len = match err.len {
+                    0 => token.chars().count(),
+                    len => err.input[..len].chars().count(),
+                };
+                Err(anyhow!(
+                    "invalid expression: {} at column {}\n  {}\n  {}{}",
+                    message,
+                    column + 1,
+                    expr,
+                    " ".repeat(column),
+                    "^".repeat(len.max(1))
+                ))
+            }
+            Err(nom::Err::Incomplete(_)) => Err(anyhow!("invalid expression: incomplete")),
+        }
+    }
+    pub fn matches(&self, record: &LogcatRecord) -> bool {
+        self.root.matches(record)
+    }
+}
+
+#[test]
+fn evaluate_expression() {
+    use chrono::FixedOffset;
+
+    // Like `--tz +09:00`, times of day are compared as printed
+    let zone = FixedOffset::east_opt(9 * 3600).unwrap();
+    crate::utils::set_zone(zone);
+    let timestamp = zone
+        .with_ymd_and_hms(2024, 3, 1, 18, 10, 53)
+        .single()
+        .map(|t| t.with_timezone(&Local));
+    let record = |level: Level, tag: &str, process_name: &str, message: &str| LogcatRecord {
+        level,
+        tag: tag.to_string(),
+        pid: 1904,
+        process_name: process_name.to_string(),
+        message: message.to_string(),
+        timestamp,
+        ..LogcatRecord::default()
+    };
+    let expr =
+        Expression::parse("level>=W and (tag~Camera or process=com.foo) and not message~heartbeat")
+            .unwrap();
+    assert!(expr.matches(&record(
+        Level::Warn,
+        "Camera2",
+        "cameraserver",
+        "open failed"
+    )));
+    assert!(expr.matches(&record(Level::Error, "App", "com.foo", "crash")));
+    assert!(!expr.matches(&record(
+        Level::Info,
+        "Camera2",
+        "cameraserver",
+        "open failed"
+    )));
+    assert!(!expr.matches(&record(Level::Warn, "App", "com.bar", "crash")));
+    assert!(!expr.matches(&record(Level::Warn, "Camera2", "cameraserver", "heartbeat")));
+
+    let expr = Expression::parse(
+        r#"!(pid != 1904) && time >= 18:10 && time < "2024-03-02 18:11" || message = "a \"b\"""#,
+    )
+    .unwrap();
+    assert!(expr.matches(&record(Level::Debug, "Tag", "p", "")));
+    assert!(expr.matches(&LogcatRecord {
+        message: "a \"b\"".to_string(),
+        ..LogcatRecord::default()
+    }));
+    assert!(!expr.matches(&LogcatRecord::default()));
+    assert!(!Expression::parse("time < 18:10").unwrap().matches(&record(
+        Level::Debug,
+        "Tag",
+        "p",
+        ""
+    )));
+    assert!(Expression::parse("uid=10153 or package=''").is_ok());
+}
+
+#[test]
+fn expression_errors() {
+    let error = |expr: &str| Expression::parse(expr).unwrap_err().to_string();
+    assert_eq!(
+        error("level>=W and lvl=E"),
+        "invalid expression: unknown field 'lvl', expected one of level, tag, pid, tid, uid, process, package, message, time at column 14\n  level>=W and lvl=E\n               ^^^"
+    );
+    assert_eq!(
+        error("level>=X"),
+        "invalid expression: unknown level 'X', expected one of V D I W E F A or their names at column 8\n  level>=X\n         ^"
+    );
+    assert_eq!(
+        error("tag > Camera"),
+        "invalid expression: '>' doesn't apply to tag, use = != ~ or !~ at column 5\n  tag > Camera\n      ^"
+    );
+    assert!(error("(tag=A or tag=B").contains("unclosed '(' at column 1"));
+    assert!(error("tag=A and").contains("unexpected end of expression at column 10"));
+    assert!(error("tag=A tag=B").contains("unexpected 'tag=B' at column 7"));
+    assert!(error("pid=abc").contains("'abc' isn't a number at column 5"));
+    assert!(error(r#"message~"(""#).contains("invalid regex '('"));
+    assert!(error("tag").contains("expected one of = != ~ !~ < <= > >= after 'tag' at column 4"));
+}
@@ -0,0 +1,460 @@
+use crate::record::{Level, LogcatRecord};
+use crate::utils::message_text;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDateTime, TimeZone};
+use nom::character::complete::{one_of, u32};
+use nom::combinator::eof;
+use nom::multi::many1;
+use nom::sequence::{terminated, tuple};
+use regex::{Regex, RegexBuilder};
+
+// logcat-style filter specs: `ActivityManager:I MyApp:D *:S`
+// A tag without a priority means `V`, `S` silences the tag entirely.
+// Tags may contain `*` wildcards, e.g. `Camera*:W`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterRule {
+    pub tag: String,
+    // None means silent
+    pub priority: Option<Level>,
+}
+
+impl FilterRule {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (tag, priority) = match spec.rsplit_once(':') {
+            Some((tag, priority)) => (tag, priority),
+            None => (spec, "V"),
+        };
+        if tag.is_empty() {
+            return Err(anyhow!("invalid filter spec '{}': empty tag", spec));
+        }
+        let priority = match priority {
+            "S" | "s" => None,
+            "" => Some(Level::Verbose),
+            p => match Level::from(p) {
+                Level::None => {
+                    return Err(anyhow!(
+                        "invalid filter spec '{}': unknown priority '{}'",
+                        spec,
+                        p
+                    ))
+                }
+                level => Some(level),
+            },
+        };
+        Ok(FilterRule {
+            tag: tag.to_string(),
+            priority,
+        })
+    }
+    fn is_wildcard(&self) -> bool {
+        self.tag.contains('*')
+    }
+    fn matches_tag(&self, tag: &str) -> bool {
+        if self.is_wildcard() {
+            wildcard_match(&self.tag, tag)
+        } else {
+            self.tag == tag
+        }
+    }
+}
+
+// Glob matching supporting only `*`
+pub fn wildcard_match(pattern: &str, s: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = s.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.is_empty()
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    pub rules: Vec<FilterRule>,
+}
+
+impl Filter {
+    // Every argument may hold several whitespace separated specs,
+    // so both `-f "ActivityManager:I *:S"` and `-f ActivityManager:I -f *:S` work.
+    pub fn parse<S: AsRef<str>>(specs: &[S]) -> Result<Self> {
+        let rules = specs
+            .iter()
+            .flat_map(|s| s.as_ref().split_whitespace())
+            .map(FilterRule::parse)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Filter { rules })
+    }
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+    // Exact tag matches win over wildcard patterns, the first matching
+    // pattern wins over later ones. Unmatched tags fall back to `V`.
+    fn priority_for(&self, tag: &str) -> Option<Level> {
+        self.rules
+            .iter()
+            .find(|r| !r.is_wildcard() && r.tag == tag)
+            .or_else(|| self.rules.iter().find(|r| r.matches_tag(tag)))
+            .map(|r| r.priority)
+            .unwrap_or(Some(Level::Verbose))
+    }
+    pub fn matches(&self, record: &LogcatRecord) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        match self.priority_for(&record.tag) {
+            Some(priority) => record.level >= priority,
+            None => false,
+        }
+    }
+}
+
+// `--grep`, `--grep-tag` and `--grep-process`: every given field has to match
+// one of its patterns, `--invert-match` keeps the records that don't
+#[derive(Clone, Debug, Default)]
+pub struct Grep {
+    message: Option<Regex>,
+    tag: Option<Regex>,
+    process: Option<Regex>,
+    invert: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GrepOptions {
+    pub ignore_case: bool,
+    pub fixed_strings: bool,
+    pub invert: bool,
+}
+
+fn grep_regex<S: AsRef<str>>(patterns: &[S], options: GrepOptions) -> Result<Option<Regex>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let pattern = patterns
+        .iter()
+        .map(|p| match options.fixed_strings {
+            true => regex::escape(p.as_ref()),
+            false => format!("(?:{})", p.as_ref()),
+        })
+        .collect::<Vec<_>>()
+        .join("|");
+    RegexBuilder::new(&pattern)
+        .case_insensitive(options.ignore_case)
+        .build()
+        .map(Some)
+        .map_err(|err| anyhow!("invalid grep pattern: {}", err))
+}
+
+impl Grep {
+    pub fn new<S: AsRef<str>>(
+        message: &[S],
+        tag: &[S],
+        process: &[S],
+        options: GrepOptions,
+    ) -> Result<Self> {
+        Ok(Grep {
+            message: grep_regex(message, options)?,
+            tag: grep_regex(tag, options)?,
+            process: grep_regex(process, options)?,
+            invert: options.invert,
+        })
+    }
+    pub fn is_empty(&self) -> bool {
+        self.message.is_none() && self.tag.is_none() && self.process.is_none()
+    }
+    // Needs the process name, so only after it has been resolved
+    pub fn matches(&self, record: &LogcatRecord) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let is_match = |regex: &Option<Regex>, text: &str| {
+            regex.as_ref().is_none_or(|regex| regex.is_match(text))
+        };
+        let matches = is_match(&self.message, &message_text(record))
+            && is_match(&self.tag, &record.tag)
+            && is_match(&self.process, &record.process_name);
+        matches != self.invert
+    }
+    // Character ranges of the message matches to highlight, none for inverted greps
+    pub fn highlights(&self, message: &str) -> Vec<(usize, usize)> {
+        let Some(regex) = self.message.as_ref().filter(|_| !self.invert) else {
+            return Vec::new();
+        };
+        regex
+            .find_iter(message)
+            .filter(|m| !m.is_empty())
+            .map(|m| {
+                let start = message[..m.start()].chars().count();
+                (start, start + m.as_str().chars().count())
+            })
+            .collect()
+    }
+}
+
+// `--since` and `--until`, records without a timestamp can't be placed and pass
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimeWindow {
+    pub since: Option<DateTime<Local>>,
+    pub until: Option<DateTime<Local>>,
+}
+
+// `1h30m` style durations, `s`, `m`, `h` and `d` units
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (_, parts) = terminated(
+        many1(tuple((u32::<_, nom::error::Error<_>>, one_of("smhd")))),
+        eof,
+    )(s)
+    .ok()?;
+    Some(
+        parts
+            .into_iter()
+            .fold(Duration::zero(), |total, (n, unit)| {
+                let n = i64::from(n);
+                total
+                    + match unit {
+                        's' => Duration::seconds(n),
+                        'm' => Duration::minutes(n),
+                        'h' => Duration::hours(n),
+                        _ => Duration::days(n),
+                    }
+            }),
+    )
+}
+
+// `10m` ago, `18:10[:00[.000]]` today, `08-30 18:10:00` this year or a full
+// `2024-08-30 18:10:00`, the same forms logcat prints. `now` is the source's
+// clock, times are in its zone like the log's.
+pub fn parse_time_bound(text: &str, now: DateTime<FixedOffset>) -> Result<DateTime<Local>> {
+    if let Some(duration) = parse_duration(text) {
+        return Ok((now - duration).with_timezone(&Local));
+    }
+    let text = text.trim();
+    let candidates = [
+        text.to_string(),
+        format!("{}-{}", now.year(), text),
+        format!("{} {}", now.format("%Y-%m-%d"), text),
+    ];
+    candidates
+        .iter()
+        .find_map(|candidate| {
+            ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M"]
+                .iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(candidate, format).ok())
+        })
+        .and_then(|time| now.offset().from_local_datetime(&time).single())
+        .map(|time| time.with_timezone(&Local))
+        .ok_or_else(|| {
+            anyhow!(
+                "invalid time '{}', expected a duration like 10m or 1h30m, HH:MM[:SS], MM-DD HH:MM:SS or YYYY-MM-DD HH:MM:SS",
+                text
+            )
+        })
+}
+
+impl TimeWindow {
+    pub fn parse(
+        since: Option<&str>,
+        until: Option<&str>,
+        now: DateTime<FixedOffset>,
+    ) -> Result<Self> {
+        let window = TimeWindow {
+            since: since.map(|s| parse_time_bound(s, now)).transpose()?,
+            until: until.map(|u| parse_time_bound(u, now)).transpose()?,
+        };
+        if let (Some(since), Some(until)) = (window.since, window.until) {
+            if since > until {
+                return Err(anyhow!("--since {} is after --until {}", since, until));
+            }
+        }
+        Ok(window)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+    pub fn contains(&self, record: &LogcatRecord) -> bool {
+        let Some(time) = record.timestamp else {
+            return true;
+        };
+        self.since.is_none_or(|since| time >= since) && self.until.is_none_or(|until| time <= until)
+    }
+}
+
+#[test]
+fn parse_filter_spec() {
+    let filter = Filter::parse(&["ActivityManager:I *:S", "MyApp"]).unwrap();
+    assert_eq!(
+        filter.rules,
+        vec![
+            FilterRule {
+                tag: "ActivityManager".to_string(),
+                priority: Some(Level::Info)
+            },
+            FilterRule {
+                tag: "*".to_string(),
+                priority: None
+            },
+            FilterRule {
+                tag: "MyApp".to_string(),
+                priority: Some(Level::Verbose)
+            },
+        ]
+    );
+    assert!(Filter::parse(&["Tag:X"]).is_err());
+    assert!(Filter::parse(&[":D"]).is_err());
+    for priority in ["d", "D", "debug", "DEBUG", "Debug"] {
+        let rule = FilterRule::parse(&format!("Tag:{}", priority)).unwrap();
+        assert_eq!(rule.priority, Some(Level::Debug));
+    }
+}
+
+#[test]
+fn filter_by_level() {
+    let record = |tag: &str, level: Level| LogcatRecord {
+        tag: tag.to_string(),
+        level,
+        ..LogcatRecord::default()
+    };
+    let filter = Filter::parse(&["ActivityManager:I *:S"]).unwrap();
+    assert!(filter.matches(&record("ActivityManager", Level::Info)));
+    assert!(filter.matches(&record("ActivityManager", Level::Error)));
+    assert!(!filter.matches(&record("ActivityManager", Level::Debug)));
+    assert!(!filter.matches(&record("NetworkMonitor", Level::Assert)));
+
+    let filter = Filter::parse(&["*:W", "Chatty:S"]).unwrap();
+    assert!(filter.matches(&record("NetworkMonitor", Level::Warn)));
+    assert!(!filter.matches(&record("NetworkMonitor", Level::Info)));
+    assert!(!filter.matches(&record("Chatty", Level::Fatal)));
+
+    assert!(Filter::default().matches(&record("Any", Level::None)));
+}
+
+#[test]
+fn filter_wildcard_tag() {
+    let record = |tag: &str, level: Level| LogcatRecord {
+        tag: tag.to_string(),
+        level,
+        ..LogcatRecord::default()
+    };
+    let filter = Filter::parse(&["Camera*:D", "CameraService:E", "*:S"]).unwrap();
+    assert!(filter.matches(&record("Camera2Client", Level::Debug)));
+    assert!(!filter.matches(&record("Camera2Client", Level::Verbose)));
+    assert!(!filter.matches(&record("CameraService", Level::Warn)));
+    assert!(!filter.matches(&record("AudioFlinger", Level::Error)));
+    assert!(wildcard_match("*Manager*", "ActivityManagerService"));
+    assert!(wildcard_match("a*c", "abc"));
+    assert!(!wildcard_match("a*c", "abcd"));
+}
+
+#[test]
+fn grep_fields() {
+    let record = |tag: &str, process_name: &str, message: &str| LogcatRecord {
+        tag: tag.to_string(),
+        process_name: process_name.to_string(),
+        message: message.to_string(),
+        ..LogcatRecord::default()
+    };
+    let options = GrepOptions::default();
+    let grep = Grep::new(&["timeout|refused"], &["^Network"], &[], options).unwrap();
+    assert!(grep.matches(&record(
+        "NetworkMonitor",
+        "system_server",
+        "connect refused"
+    )));
+    assert!(!grep.matches(&record("NetworkMonitor", "system_server", "connected")));
+    assert!(!grep.matches(&record("WifiNetwork", "system_server", "timeout")));
+    assert_eq!(grep.highlights("élan timeout"), vec![(5, 12)]);
+
+    let grep = Grep::new(
+        &["a.b"],
+        &[],
+        &["Camera"],
+        GrepOptions {
+            ignore_case: true,
+            fixed_strings: true,
+            invert: true,
+        },
+    )
+    .unwrap();
+    assert!(!grep.matches(&record("Tag", "android.hardware.camera", "got A.B")));
+    assert!(grep.matches(&record("Tag", "android.hardware.camera", "got axb")));
+    assert!(grep.matches(&record("Tag", "surfaceflinger", "got a.b")));
+    assert!(grep.highlights("a.b").is_empty());
+
+    assert!(Grep::new(&["("], &[], &[], options).is_err());
+    assert!(Grep::default().matches(&record("Any", "any", "")));
+}
+
+#[test]
+fn time_window() {
+    let now = Local
+        .with_ymd_and_hms(2024, 8, 30, 18, 30, 0)
+        .unwrap()
+        .fixed_offset();
+    let at = |h, m, s| Local.with_ymd_and_hms(2024, 8, 30, h, m, s).unwrap();
+    assert_eq!(parse_time_bound("10m", now).unwrap(), at(18, 20, 0));
+    assert_eq!(parse_time_bound("1h30m", now).unwrap(), at(17, 0, 0));
+    assert_eq!(parse_time_bound("18:10", now).unwrap(), at(18, 10, 0));
+    assert_eq!(
+        parse_time_bound("08-30 18:10:53", now).unwrap(),
+        at(18, 10, 53)
+    );
+    assert_eq!(
+        parse_time_bound("2024-08-30 18:10:53.250", now).unwrap(),
+        at(18, 10, 53) + Duration::milliseconds(250)
+    );
+    assert!(parse_time_bound("yesterday", now).is_err());
+    assert!(parse_time_bound("10x", now).is_err());
+
+    let window = TimeWindow::parse(Some("18:10"), Some("18:11"), now).unwrap();
+    let record = |time| LogcatRecord {
+        timestamp: time,
+        ..LogcatRecord::default()
+    };
+    assert!(window.contains(&record(Some(at(18, 10, 30)))));
+    assert!(!window.contains(&record(Some(at(18, 9, 59)))));
+    assert!(!window.contains(&record(Some(at(18, 11, 1)))));
+    assert!(window.contains(&record(None)));
+    assert!(TimeWindow::parse(Some("18:11"), Some("18:10"), now).is_err());
+}
+
+#[test]
+fn time_window_on_replay() {
+    use crate::parser::{DeviceClock, LogFormat, LogcatParser};
+
+    // Saved a year ago, bounds are relative to when the log was written
+    let at = |d, h, m| Local.with_ymd_and_hms(2023, 12, d, h, m, 0).unwrap();
+    let mut clock = DeviceClock::default();
+    clock.reference = Some(at(31, 23, 30).fixed_offset());
+    let window = TimeWindow::parse(Some("10m"), Some("23:25"), clock.now()).unwrap();
+    assert_eq!(window.since, Some(at(31, 23, 20)));
+    assert_eq!(window.until, Some(at(31, 23, 25)));
+    assert_eq!(
+        parse_time_bound("12-30 08:00", clock.now()).unwrap(),
+        at(30, 8, 0)
+    );
+    let mut parser = LogcatParser::new(LogFormat::Threadtime);
+    parser.clock = clock;
+    let mut contains = |line: &str| window.contains(&parser.try_parse(line).unwrap());
+    assert!(!contains("12-31 23:19:59.000  1904  6916 D A: OK"));
+    assert!(contains("12-31 23:22:00.000  1904  6916 D A: OK"));
+    assert!(!contains("12-31 23:26:00.000  1904  6916 D A: OK"));
+
+    // A device's times of day are on its own clock
+    let zone = FixedOffset::east_opt(9 * 3600).unwrap();
+    let mut clock = DeviceClock::default();
+    clock.zone = Some(zone);
+    clock.reference = zone.with_ymd_and_hms(2024, 1, 1, 0, 30, 0).single();
+    assert_eq!(
+        parse_time_bound("00:10", clock.now()).unwrap(),
+        zone.with_ymd_and_hms(2024, 1, 1, 0, 10, 0).unwrap()
+    );
+}
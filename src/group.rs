@@ -0,0 +1,71 @@
+use crate::record::LogcatRecord;
+
+// Android logs every line of a multi-line message, e.g. a stack trace, as its
+// own entry. Back-to-back entries sharing pid, tid, tag, level and timestamp
+// are merged into one record whose message lines are joined with '\n'.
+#[derive(Debug, Default)]
+pub struct RecordGrouper {
+    pending: Option<LogcatRecord>,
+}
+
+fn continues(record: &LogcatRecord, next: &LogcatRecord) -> bool {
+    record.pid == next.pid
+        && record.tid == next.tid
+        && record.tag == next.tag
+        && record.level == next.level
+        && record.timestamp == next.timestamp
+        && record.device == next.device
+        && record.fields.is_empty()
+        && next.fields.is_empty()
+}
+
+impl RecordGrouper {
+    // Returns the previous record once `record` doesn't continue it
+    pub fn push(&mut self, record: LogcatRecord) -> Option<LogcatRecord> {
+        match &mut self.pending {
+            Some(pending) if continues(pending, &record) => {
+                pending.message.push('\n');
+                pending.message.push_str(&record.message);
+                pending.raw.push('\n');
+                pending.raw.push_str(&record.raw);
+                None
+            }
+            _ => self.pending.replace(record),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_none()
+    }
+    // The last record can't know it's complete, it's flushed when the stream goes quiet
+    pub fn flush(&mut self) -> Option<LogcatRecord> {
+        self.pending.take()
+    }
+}
+
+#[test]
+fn group_stack_trace() {
+    let line = |tid: u32, message: &str| LogcatRecord {
+        pid: 1904,
+        tid,
+        tag: "AndroidRuntime".to_string(),
+        message: message.to_string(),
+        ..LogcatRecord::default()
+    };
+    let mut grouper = RecordGrouper::default();
+    assert_eq!(grouper.push(line(1904, "FATAL EXCEPTION: main")), None);
+    assert_eq!(
+        grouper.push(line(1904, "java.lang.NullPointerException")),
+        None
+    );
+    assert_eq!(
+        grouper.push(line(1904, "\tat com.example.Main.run(Main.java:42)")),
+        None
+    );
+    let trace = grouper.push(line(1910, "other thread")).unwrap();
+    assert_eq!(
+        trace.message,
+        "FATAL EXCEPTION: main\njava.lang.NullPointerException\n\tat com.example.Main.run(Main.java:42)"
+    );
+    assert_eq!(grouper.flush().unwrap().message, "other thread");
+    assert!(grouper.is_empty());
+}
@@ -1,26 +1,59 @@
+use adb::{AdbClient, DeviceTracker};
 use anyhow::anyhow;
 use anyhow::{Ok, Result};
 use clap::Parser;
-use data::ProcessRecords;
-use parser::LogcatParser;
-use process_stream::{Process, ProcessExt, StreamExt};
+use config::Config;
+use data::{ProcessRecords, ThreadNames};
+use events::EventTags;
+use expr::Expression;
+use filter::{Filter, Grep, GrepOptions, TimeWindow};
+use group::RecordGrouper;
+use output::{Output, OutputFormat, RecordWriter};
+use parser::{
+    parse_device_date, parse_utc_offset, ActivityManagerParser, DeviceClock, LogFormat,
+    LogcatParser, DEVICE_DATE_FORMAT,
+};
+use process_stream::tokio_stream::wrappers::UnboundedReceiverStream;
+use process_stream::tokio_stream::StreamMap;
+use process_stream::StreamExt;
+use ratatui::crossterm::event::Event;
+use record::{LogBuffer, ProcessEvent};
+use source::{Source, SourceItem, SourceStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tui::{Tui, View};
 use utils::Terminal;
 
+mod adb;
+mod binary;
+mod config;
 mod data;
+mod events;
+mod expr;
+mod filter;
+mod group;
+mod output;
 mod parser;
 mod record;
+mod source;
+mod theme;
+mod tui;
 mod utils;
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[clap(name = "logcat")]
 struct Args {
-    #[clap(long)]
-    pub hide_timestamp: bool,
-    #[clap(long, default_value_t = true)]
-    pub hide_date: bool,
-    #[clap(long, short = 'p', default_value_t = true)]
-    pub use_process_name: bool,
-    #[clap(long)]
-    pub bright_colors: bool,
+    // Switches take an optional value, `--hide-date=false` overrides the config
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub hide_timestamp: Option<bool>,
+    // On unless turned off
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub hide_date: Option<bool>,
+    // On unless turned off
+    #[clap(long, short = 'p', num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub use_process_name: Option<bool>,
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub bright_colors: Option<bool>,
     #[clap(long, short)]
     pub filter: Vec<String>,
     #[clap(long)]
@@ -29,14 +62,129 @@ struct Args {
     pub tag_width: Option<usize>,
     #[clap(long)]
     pub pid_width: Option<usize>,
+    #[clap(long)]
+    pub package: Vec<String>,
+    // Regex matched against the message, matches are highlighted
+    #[clap(long, short = 'g')]
+    pub grep: Vec<String>,
+    #[clap(long)]
+    pub grep_tag: Vec<String>,
+    // Matched against the resolved process name
+    #[clap(long)]
+    pub grep_process: Vec<String>,
+    #[clap(long)]
+    pub ignore_case: bool,
+    // Patterns are plain substrings instead of regexes
+    #[clap(long, short = 'F')]
+    pub fixed_strings: bool,
+    // Keep the records that don't match the `--grep` patterns
+    #[clap(long, short = 'v')]
+    pub invert_match: bool,
+    // Boolean filter over the record's fields, e.g.
+    // `level>=W and (tag~Camera or process=com.foo) and not message~heartbeat`
+    #[clap(long, short = 'e')]
+    pub expr: Option<String>,
+    // Drop records before this time, `10m` ago, `18:10:00` today or a full date,
+    // passed to logcat as `-T` when reading live
+    #[clap(long)]
+    pub since: Option<String>,
+    // Drop records after this time, same forms as `--since`
+    #[clap(long)]
+    pub until: Option<String>,
+    // Print timestamps in UTC instead of the host's zone
+    #[clap(long, conflicts_with = "tz")]
+    pub utc: bool,
+    // Print timestamps at this UTC offset, e.g. `+0530` or `-08:00`
+    #[clap(long)]
+    pub tz: Option<String>,
+    // Read a saved log instead of running `adb logcat`, `-` reads stdin
+    #[clap(long, short = 'i')]
+    pub input: Option<PathBuf>,
+    #[clap(value_name = "INPUT", conflicts_with = "input")]
+    pub input_positional: Option<PathBuf>,
+    // Saved `adb shell ps` output used to resolve process names when replaying
+    #[clap(long)]
+    pub ps_dump: Option<PathBuf>,
+    // Saved `adb shell pm list packages -U` output used to name UIDs when replaying
+    #[clap(long)]
+    pub packages_dump: Option<PathBuf>,
+    #[clap(long, short = 's', conflicts_with = "all_devices")]
+    pub serial: Option<String>,
+    // Stream from every attached device at once, including ones attached later
+    #[clap(long)]
+    pub all_devices: bool,
+    // `logcat -v` format of saved logs, detected per line by default
+    #[clap(long, value_enum, default_value_t = LogFormat::Auto)]
+    pub format: LogFormat,
+    // Count lines that can't be parsed instead of printing them dimmed
+    #[clap(long)]
+    pub hide_unparsed: bool,
+    // Report every line that can't be parsed and fail at the end
+    #[clap(long, conflicts_with = "hide_unparsed")]
+    pub strict: bool,
+    #[clap(long, short = 'o', value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+    // Records are labeled with the buffer named by the last divider, which
+    // logcat only prints at every change of buffer with `-D`
+    #[clap(
+        long,
+        short = 'b',
+        value_enum,
+        help = "Buffers to read, saved text logs need `logcat -D` for correct buffer labels"
+    )]
+    pub buffer: Vec<LogBuffer>,
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub show_buffer: Option<bool>,
+    // Thread name column, e.g. `RenderThread`, the TID until it's resolved
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub show_thread: Option<bool>,
+    #[clap(long)]
+    pub thread_width: Option<usize>,
+    // Saved /system/etc/event-log-tags used to decode the `events` buffer,
+    // fetched from the device when reading it live
+    #[clap(long)]
+    pub event_tags: Option<PathBuf>,
+    // Read `logcat -B` entries, keeping nanoseconds, uid and log id,
+    // saved inputs must have been captured with `adb exec-out logcat -B`
+    #[clap(long)]
+    pub binary: bool,
+    // Defaults to $XDG_CONFIG_HOME/r1gcat/config.toml
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+    // `[profile.<name>]` section of the config applied on top of its defaults
+    #[clap(long)]
+    pub profile: Option<String>,
+    // dark, light, solarized or a `[themes.<name>]` section of the config
+    #[clap(long)]
+    pub theme: Option<String>,
+    // Full screen view with scrollback, pausing and live filter and search editing
+    #[clap(long, conflicts_with = "output")]
+    pub tui: bool,
+    // Entries the TUI keeps to scroll back through
+    #[clap(long, default_value_t = tui::DEFAULT_HISTORY)]
+    pub history: usize,
 }
 impl From<Args> for Terminal {
     fn from(args: Args) -> Self {
         let mut terminal = Terminal::default();
-        terminal.hide_timestamp = args.hide_timestamp;
-        terminal.hide_date = args.hide_date;
-        terminal.use_process_name = args.use_process_name;
-        terminal.bright_colors = args.bright_colors;
+        if let Some(hide_timestamp) = args.hide_timestamp {
+            terminal.hide_timestamp = hide_timestamp;
+        }
+        if let Some(hide_date) = args.hide_date {
+            terminal.hide_date = hide_date;
+        }
+        if let Some(use_process_name) = args.use_process_name {
+            terminal.use_process_name = use_process_name;
+        }
+        if let Some(bright_colors) = args.bright_colors {
+            terminal.bright_colors = bright_colors;
+        }
+        if let Some(show_buffer) = args.show_buffer {
+            terminal.show_buffer = show_buffer;
+        }
+        if let Some(show_thread) = args.show_thread {
+            terminal.show_thread = show_thread;
+        }
         if let Some(width) = args.process_name_width {
             terminal.process_name_width = width;
         }
@@ -46,43 +194,526 @@ impl From<Args> for Terminal {
         if let Some(width) = args.pid_width {
             terminal.pid_width = width;
         }
+        if let Some(width) = args.thread_width {
+            terminal.thread_width = width;
+        }
         terminal
     }
 }
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args: Args = Args::parse();
-    let adb_path = utils::adb()?;
-    let process_records = ProcessRecords {
-        enabled: args.use_process_name,
-        adb_cmd: adb_path.to_str().unwrap_or("adb").to_string(),
+struct Device {
+    label: Option<String>,
+    live: bool,
+    process_records: ProcessRecords,
+    thread_names: ThreadNames,
+    parser: LogcatParser,
+    grouper: RecordGrouper,
+    event_tags: EventTags,
+    lines: usize,
+    unparsed: usize,
+    // `--since` and `--until` on the device's clock
+    window: TimeWindow,
+    // When what's held back is complete, GROUP_TIMEOUT after the source's last item
+    flush_at: Option<tokio::time::Instant>,
+}
+impl Device {
+    fn is_holding(&self) -> bool {
+        !self.grouper.is_empty() || self.parser.is_pending()
+    }
+}
+// How long a record waits for continuation lines before it's printed
+const GROUP_TIMEOUT: Duration = Duration::from_millis(50);
+fn flush_groups(devices: &mut [Device], output: &mut Output) -> Result<()> {
+    for device in devices.iter_mut() {
+        if let Some(record) = device.grouper.flush() {
+            output.print(&record)?;
+        }
+    }
+    Ok(())
+}
+// Everything needed to follow one source, the streams go into the `StreamMap`s
+async fn open_device(
+    args: &Args,
+    mut source: Source,
+) -> Result<(Device, SourceStream, UnboundedReceiverStream<ProcessEvent>)> {
+    let (sender, events) = mpsc::unbounded_channel();
+    let mut process_records = ProcessRecords {
+        enabled: args.use_process_name.unwrap_or(true),
+        live: source.is_live(),
+        client: source.client().cloned().unwrap_or_default(),
+        packages: args.package.clone(),
+        events: Some(sender),
         ..ProcessRecords::default()
     };
-    let mut terminal: Terminal = args.into();
-    let logcat_parser = LogcatParser {};
-    let mut process: Process = vec![adb_path.to_str().unwrap_or("adb"), "logcat"].into();
+    // Saved logs were written up to their modification date, in an unknown zone
+    let mut clock = DeviceClock::default();
+    match &source {
+        Source::Adb { .. } => {
+            let date = process_records.shell(&["date", DEVICE_DATE_FORMAT]).await;
+            let date = date.as_deref().and_then(parse_device_date);
+            if let Some(date) = date {
+                clock.reference = Some(date);
+                clock.zone = Some(*date.offset());
+                process_records.skew = date.with_timezone(&chrono::Local) - chrono::Local::now();
+            }
+        }
+        Source::File { path, .. } => {
+            clock.reference = std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(|t| chrono::DateTime::<chrono::Local>::from(t).fixed_offset());
+        }
+        Source::Stdin { .. } => {}
+    }
+    let window = TimeWindow::parse(args.since.as_deref(), args.until.as_deref(), clock.now())?;
+    if let Source::Adb { since, .. } = &mut source {
+        *since = window.since;
+    }
+    if let Some(ps_dump) = &args.ps_dump {
+        process_records.load_ps_output(&std::fs::read_to_string(ps_dump)?, Instant::now());
+    }
+    if let Some(packages_dump) = &args.packages_dump {
+        process_records.load_packages_output(&std::fs::read_to_string(packages_dump)?);
+    }
+    let event_tags = match &args.event_tags {
+        Some(path) => EventTags::parse(&std::fs::read_to_string(path)?),
+        None if source.is_live()
+            && args
+                .buffer
+                .iter()
+                .any(|b| matches!(b, LogBuffer::Events | LogBuffer::All)) =>
+        {
+            EventTags::parse(
+                &process_records
+                    .shell(&["cat", "/system/etc/event-log-tags"])
+                    .await
+                    .unwrap_or_default(),
+            )
+        }
+        None => EventTags::default(),
+    };
     let process_records_clone = process_records.clone();
     tokio::spawn(async move {
         process_records_clone.update_process_record().await;
     });
-    let mut stream = process.spawn_and_stream()?;
-    while let Some(line) = stream.next().await {
-        match line {
-            process_stream::ProcessItem::Output(line) => {
-                let record = logcat_parser.try_parse(&line);
-                if let Some(mut record) = record {
-                    record.process_name = process_records.get_process_name(record.pid);
-                    // println!("{:?}",record.process_name)
-                    terminal.print(&record)?;
+    let process_records_clone = process_records.clone();
+    tokio::spawn(async move {
+        process_records_clone.resolve_process_names().await;
+    });
+    // JSON output carries the name even without the column
+    let thread_names = ThreadNames {
+        enabled: args.show_thread == Some(true) || args.output == OutputFormat::Json,
+        live: source.is_live(),
+        client: process_records.client.clone(),
+        ..ThreadNames::default()
+    };
+    let thread_names_clone = thread_names.clone();
+    tokio::spawn(async move {
+        thread_names_clone.resolve_thread_names().await;
+    });
+    let stream = source.stream(&clock).await?;
+    let mut parser = LogcatParser::new(args.format);
+    parser.clock = clock;
+    // Only label lines when several devices are interleaved
+    let label = if args.all_devices {
+        source.serial().map(ToString::to_string)
+    } else {
+        None
+    };
+    let device = Device {
+        label,
+        live: source.is_live(),
+        process_records,
+        thread_names,
+        parser,
+        grouper: RecordGrouper::default(),
+        event_tags,
+        lines: 0,
+        unparsed: 0,
+        window,
+        flush_at: None,
+    };
+    Ok((device, stream, UnboundedReceiverStream::new(events)))
+}
+// Pending forever without a tracker so `select!` can always poll it
+async fn next_devices(tracker: &mut Option<DeviceTracker>) -> Option<Result<Vec<String>>> {
+    match tracker {
+        Some(tracker) => tracker.next().await,
+        None => std::future::pending().await,
+    }
+}
+// Same for key presses outside the TUI
+async fn next_input(input: &mut Option<mpsc::UnboundedReceiver<Event>>) -> Option<Event> {
+    match input {
+        Some(input) => input.recv().await,
+        None => std::future::pending().await,
+    }
+}
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args: Args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+    config.apply(&mut args)?;
+    let theme = config.theme(args.theme.as_deref())?;
+    // The TUI filters what it shows so the filter can be changed later
+    let filter = match args.tui {
+        true => Filter::default(),
+        false => Filter::parse(&args.filter)?,
+    };
+    // Checked before connecting, every device resolves it against its own clock
+    let window = TimeWindow::parse(
+        args.since.as_deref(),
+        args.until.as_deref(),
+        chrono::Local::now().fixed_offset(),
+    )?;
+    if let Some(tz) = &args.tz {
+        utils::set_zone(
+            parse_utc_offset(tz)
+                .ok_or_else(|| anyhow!("invalid --tz '{}', expected an offset like +0530", tz))?,
+        );
+    } else if args.utc {
+        utils::set_zone(chrono::FixedOffset::east_opt(0).unwrap());
+    }
+    let expression = args.expr.as_deref().map(Expression::parse).transpose()?;
+    let grep = Grep::new(
+        &args.grep,
+        &args.grep_tag,
+        &args.grep_process,
+        GrepOptions {
+            ignore_case: args.ignore_case,
+            fixed_strings: args.fixed_strings,
+            invert: args.invert_match,
+        },
+    )?;
+    let client = AdbClient::default();
+    let logcat_source = |serial: Option<String>| {
+        let mut logcat_args = vec!["logcat".to_string()];
+        // Binary entries carry their buffer, interleaved text ones need a
+        // divider at every switch. Only asked for when needed, as logcat
+        // before Android 7 rejects `-D`.
+        let several = args.buffer.len() > 1
+            || args
+                .buffer
+                .iter()
+                .any(|b| matches!(b, LogBuffer::All | LogBuffer::Default));
+        if args.binary {
+            logcat_args.push("-B".to_string());
+        } else if several {
+            logcat_args.push("-D".to_string());
+        }
+        for buffer in &args.buffer {
+            logcat_args.extend(["-b".to_string(), buffer.to_string()]);
+        }
+        Source::Adb {
+            client: client.with_serial(serial),
+            args: logcat_args,
+            binary: args.binary,
+            since: None,
+        }
+    };
+    let sources = match args.input.clone().or(args.input_positional.clone()) {
+        Some(path) if path == Path::new("-") => vec![Source::Stdin {
+            binary: args.binary,
+        }],
+        Some(path) => vec![Source::File {
+            path,
+            binary: args.binary,
+        }],
+        None if args.all_devices => {
+            let serials = client.devices().await?;
+            if serials.is_empty() {
+                return Err(anyhow!("no devices attached"));
+            }
+            serials
+                .into_iter()
+                .map(|s| logcat_source(Some(s)))
+                .collect()
+        }
+        None => vec![logcat_source(args.serial.clone())],
+    };
+    let mut tracker = match sources.first() {
+        Some(source) if source.is_live() && args.all_devices => Some(client.track_devices().await?),
+        _ => None,
+    };
+    let mut devices = Vec::new();
+    let mut streams = StreamMap::new();
+    let mut process_events = StreamMap::new();
+    for (i, source) in sources.into_iter().enumerate() {
+        let (device, stream, events) = open_device(&args, source).await?;
+        streams.insert(i, stream);
+        process_events.insert(i, events);
+        devices.push(device);
+    }
+    // Unparsed lines can't be attributed to a tag or process, so they only
+    // pass through when nothing is being filtered
+    let show_unparsed = !args.hide_unparsed
+        && filter.is_empty()
+        && grep.is_empty()
+        && expression.is_none()
+        && window.is_empty()
+        && args.package.is_empty()
+        && args.output == OutputFormat::Text;
+    let strict = args.strict;
+    let mut output = match args.output {
+        OutputFormat::Text if args.tui => {
+            let mut layout: Terminal = args.clone().into();
+            layout.theme = theme;
+            layout.grep = grep.clone();
+            layout.device_width = devices
+                .iter()
+                .filter_map(|d| d.label.as_ref().map(|l| l.chars().count()))
+                .max()
+                .unwrap_or(0);
+            Output::Tui(Box::new(Tui::new(View::new(
+                layout,
+                &args.filter,
+                args.history,
+            )?)?))
+        }
+        OutputFormat::Text => {
+            let mut terminal: Terminal = args.clone().into();
+            terminal.theme = theme;
+            terminal.grep = grep.clone();
+            terminal.device_width = devices
+                .iter()
+                .filter_map(|d| d.label.as_ref().map(|l| l.chars().count()))
+                .max()
+                .unwrap_or(0);
+            Output::Terminal(Box::new(terminal))
+        }
+        format => Output::Records(Box::new(RecordWriter::new(format)?)),
+    };
+    let mut input = match &output {
+        Output::Tui(_) => Some(tui::input_events()),
+        _ => None,
+    };
+    let mut redraw = tokio::time::interval(Duration::from_millis(50));
+    redraw.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    // The TUI stays open at the end of saved logs until it's quit
+    let mut ended = false;
+    let am_parser = ActivityManagerParser {};
+    // Live sources never end, Ctrl-C still reports what was hidden
+    let interrupt = tokio::signal::ctrl_c();
+    tokio::pin!(interrupt);
+    // One timer for every device, so traffic from one can't postpone another's flush
+    let group_timeout = tokio::time::sleep(GROUP_TIMEOUT);
+    tokio::pin!(group_timeout);
+    loop {
+        let flush_at = devices
+            .iter()
+            .filter(|d| d.is_holding())
+            .filter_map(|d| d.flush_at)
+            .min();
+        if let Some(at) = flush_at.filter(|at| *at != group_timeout.deadline()) {
+            group_timeout.as_mut().reset(at);
+        }
+        let next = tokio::select! {
+            next = streams.next(), if !ended => next,
+            _ = &mut interrupt => break,
+            Some(event) = next_input(&mut input) => {
+                if let Output::Tui(tui) = &mut output {
+                    if !tui.view.handle(event) {
+                        break;
+                    }
+                }
+                continue;
+            }
+            _ = redraw.tick(), if input.is_some() => {
+                if let Output::Tui(tui) = &mut output {
+                    tui.draw()?;
+                }
+                continue;
+            }
+            // A `long` entry piped in live is complete once its source goes quiet
+            _ = &mut group_timeout, if flush_at.is_some() => {
+                let now = tokio::time::Instant::now();
+                let due = |d: &Device| d.flush_at.is_none_or(|at| at <= now);
+                match devices.iter().position(|d| d.parser.is_pending() && due(d)) {
+                    Some(i) => Some((i, SourceItem::Flush)),
+                    None => {
+                        for device in devices.iter_mut().filter(|d| due(d)) {
+                            if let Some(record) = device.grouper.flush() {
+                                output.print(&record)?;
+                            }
+                        }
+                        continue;
+                    }
                 }
             }
-            process_stream::ProcessItem::Error(err) => {
+            // Followed packages starting or dying without ActivityManager saying so
+            Some((i, event)) = process_events.next() => {
+                let device = &mut devices[i];
+                if let ProcessEvent::Died { pid, .. } = &event {
+                    device.thread_names.forget(*pid);
+                }
+                if device.process_records.is_package_process(event.name()) {
+                    if let Some(record) = device.grouper.flush() {
+                        output.print(&record)?;
+                    }
+                    let text = match &event {
+                        ProcessEvent::Started { pid, name } => format!("{} ({}) started", name, pid),
+                        ProcessEvent::Died { pid, name } => format!("{} ({}) died", name, pid),
+                    };
+                    output.print_separator(device.label.as_deref(), &text)?;
+                }
+                continue;
+            }
+            Some(serials) = next_devices(&mut tracker) => {
+                // Devices seen before keep their reconnecting stream
+                for serial in serials? {
+                    if devices.iter().any(|d| d.label.as_ref() == Some(&serial)) {
+                        continue;
+                    }
+                    if let Output::Terminal(terminal) = &mut output {
+                        terminal.device_width = terminal.device_width.max(serial.chars().count());
+                    }
+                    let (device, stream, events) =
+                        open_device(&args, logcat_source(Some(serial))).await?;
+                    streams.insert(devices.len(), stream);
+                    process_events.insert(devices.len(), events);
+                    devices.push(device);
+                }
+                continue;
+            }
+        };
+        let Some((i, item)) = next else {
+            if input.is_some() {
+                ended = true;
+                flush_groups(&mut devices, &mut output)?;
+                output.print_separator(None, "end of input")?;
+                continue;
+            }
+            break;
+        };
+        let Device {
+            label,
+            live,
+            process_records,
+            thread_names,
+            parser,
+            grouper,
+            event_tags,
+            lines,
+            unparsed,
+            window,
+            flush_at,
+        } = &mut devices[i];
+        *flush_at = Some(tokio::time::Instant::now() + GROUP_TIMEOUT);
+        let record = match item {
+            SourceItem::Output(line) => {
+                *lines += 1;
+                let record = parser.try_parse(&line);
+                if record.is_none() && !parser.is_pending() {
+                    if let Some(record) = grouper.flush() {
+                        output.print(&record)?;
+                    }
+                    if let Some(divider) = LogcatParser::parse_divider(&line) {
+                        output.print_separator(label.as_deref(), divider)?;
+                        continue;
+                    }
+                    *unparsed += 1;
+                    if strict {
+                        output.warn(
+                            label.as_deref(),
+                            &format!("unparsed line {}: {}", lines, line),
+                        );
+                    } else if show_unparsed {
+                        output.print_unparsed(label.as_deref(), &line)?;
+                    }
+                }
+                record
+            }
+            SourceItem::Record(record) => Some(*record),
+            SourceItem::Flush => parser.flush(),
+            // adb's own stderr, e.g. `- waiting for device -`
+            SourceItem::Error(err) if *live => {
+                output.warn(label.as_deref(), &err);
+                None
+            }
+            SourceItem::Error(err) => {
                 return Err(anyhow!(err));
             }
-            process_stream::ProcessItem::Exit(code) => {
+            // Live sources restart on their own, mark the gap
+            SourceItem::Exit(code) if *live => {
+                if let Some(record) = grouper.flush() {
+                    output.print(&record)?;
+                }
+                process_records.reset();
+                thread_names.clear();
+                output.print_separator(
+                    label.as_deref(),
+                    &format!("logcat stopped ({}), waiting for device", code),
+                )?;
+                None
+            }
+            SourceItem::Exit(code) => {
                 return Err(anyhow!("exit code:{:?}", code));
             }
+        };
+        let Some(mut record) = record else {
+            continue;
+        };
+        // Binary events carry the tag number, names come from event-log-tags.
+        // Decoded before any checks, `--grep` and expressions see the fields.
+        if !event_tags.is_empty() {
+            event_tags.name(&mut record);
+            event_tags.decode(&mut record);
+        }
+        let event = am_parser.try_parse(&record);
+        if let Some(event) = &event {
+            process_records.handle_process_event(event, record.timestamp);
+            if let ProcessEvent::Died { pid, .. } = event {
+                thread_names.forget(*pid);
+            }
+        }
+        record.uid = process_records.get_uid(record.pid, record.uid, record.timestamp);
+        record.package = process_records.package_for(record.pid, record.uid, record.timestamp);
+        // Processes under the app's UID belong to it whatever they are called
+        let about_package = event
+            .as_ref()
+            .map(|e| e.name())
+            .into_iter()
+            .chain(record.package.as_deref())
+            .any(|name| process_records.is_package_process(name));
+        if !about_package && !process_records.is_followed(record.pid) {
+            continue;
+        }
+        if !window.contains(&record) {
+            continue;
+        }
+        if !filter.matches(&record) {
+            continue;
         }
+        // Named after what ran under the PID when the line was logged
+        record.process_name = process_records.get_process_name(record.pid, record.timestamp);
+        // Processes without a cmdline are attributed to their app
+        if let Some(package) = record
+            .package
+            .as_ref()
+            .filter(|_| record.process_name.starts_with("pid-"))
+        {
+            record.process_name = package.clone();
+        }
+        if !grep.matches(&record) {
+            continue;
+        }
+        if !expression.as_ref().is_none_or(|e| e.matches(&record)) {
+            continue;
+        }
+        record.thread_name = thread_names.get(record.pid, record.tid);
+        record.device = label.clone();
+        if let Some(record) = grouper.push(record) {
+            output.print(&record)?;
+        }
+    }
+    flush_groups(&mut devices, &mut output)?;
+    // Leaves the TUI's alternate screen before reporting
+    drop(output);
+    let unparsed: usize = devices.iter().map(|d| d.unparsed).sum();
+    if strict && unparsed > 0 {
+        return Err(anyhow!("{} lines could not be parsed", unparsed));
+    }
+    if !show_unparsed && unparsed > 0 {
+        eprintln!("{} lines could not be parsed and were hidden", unparsed);
     }
     Ok(())
 }
@@ -0,0 +1,187 @@
+use crate::record::LogcatRecord;
+use crate::tui::Tui;
+use crate::utils::{in_zone, Terminal};
+use anyhow::Result;
+use chrono::SecondsFormat;
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    // Colored columns for humans
+    #[default]
+    Text,
+    // One JSON object per line
+    Json,
+    Csv,
+    Logfmt,
+}
+
+const COLUMNS: [&str; 7] = [
+    "timestamp",
+    "pid",
+    "tid",
+    "level",
+    "tag",
+    "process_name",
+    "message",
+];
+
+// Writes records for scripts and log shippers instead of the terminal
+pub struct RecordWriter {
+    format: OutputFormat,
+    csv: Option<csv::Writer<io::Stdout>>,
+}
+
+impl RecordWriter {
+    pub fn new(format: OutputFormat) -> Result<Self> {
+        let csv = match format {
+            OutputFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(io::stdout());
+                writer.write_record(COLUMNS)?;
+                writer.flush()?;
+                Some(writer)
+            }
+            _ => None,
+        };
+        Ok(RecordWriter { format, csv })
+    }
+    fn columns(record: &LogcatRecord) -> [String; 7] {
+        [
+            record
+                .timestamp
+                .map(|t| in_zone(t).to_rfc3339_opts(SecondsFormat::AutoSi, true))
+                .unwrap_or_default(),
+            record.pid.to_string(),
+            record.tid.to_string(),
+            record.level.to_string(),
+            record.tag.clone(),
+            record.process_name.clone(),
+            record.message.clone(),
+        ]
+    }
+    pub fn print(&mut self, record: &LogcatRecord) -> Result<()> {
+        match self.format {
+            OutputFormat::Json => {
+                let mut stdout = io::stdout().lock();
+                serde_json::to_writer(&mut stdout, record)?;
+                stdout.write_all(b"\n")?;
+                stdout.flush()?;
+            }
+            OutputFormat::Csv => {
+                if let Some(writer) = &mut self.csv {
+                    writer.write_record(Self::columns(record))?;
+                    writer.flush()?;
+                }
+            }
+            OutputFormat::Logfmt => {
+                let mut stdout = io::stdout().lock();
+                writeln!(stdout, "{}", logfmt(record))?;
+                stdout.flush()?;
+            }
+            OutputFormat::Text => unreachable!("text is rendered by Terminal"),
+        }
+        Ok(())
+    }
+}
+
+// Where records end up, separators and unparsed lines only make sense on a terminal
+pub enum Output {
+    Terminal(Box<Terminal>),
+    Records(Box<RecordWriter>),
+    Tui(Box<Tui>),
+}
+
+impl Output {
+    pub fn print(&mut self, record: &LogcatRecord) -> Result<()> {
+        match self {
+            Output::Terminal(terminal) => terminal.print(record),
+            Output::Records(writer) => writer.print(record),
+            Output::Tui(tui) => {
+                tui.view.push_record(record);
+                Ok(())
+            }
+        }
+    }
+    pub fn print_separator(&mut self, device: Option<&str>, text: &str) -> Result<()> {
+        match self {
+            Output::Terminal(terminal) => terminal.print_separator(device, text),
+            Output::Records(_) => Ok(()),
+            Output::Tui(tui) => {
+                tui.view.push_separator(device, text);
+                Ok(())
+            }
+        }
+    }
+    // Warnings go to the status line while the TUI owns the screen
+    pub fn warn(&mut self, device: Option<&str>, text: &str) {
+        match self {
+            Output::Tui(tui) => tui.view.warn(device, text),
+            _ => match device {
+                Some(device) => eprintln!("{}: {}", device, text),
+                None => eprintln!("{}", text),
+            },
+        }
+    }
+    pub fn print_unparsed(&mut self, device: Option<&str>, line: &str) -> Result<()> {
+        match self {
+            Output::Terminal(terminal) => terminal.print_unparsed(device, line),
+            Output::Records(_) => Ok(()),
+            Output::Tui(tui) => {
+                tui.view.push_unparsed(device, line);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn logfmt_value(value: &str) -> String {
+    if !value.is_empty()
+        && !value.contains(|c: char| c == ' ' || c == '=' || c == '"' || c.is_control())
+    {
+        return value.to_string();
+    }
+    format!("{:?}", value)
+}
+
+fn logfmt(record: &LogcatRecord) -> String {
+    COLUMNS
+        .iter()
+        .zip(RecordWriter::columns(record))
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{}={}", key, logfmt_value(&value)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn logfmt_record() {
+    let record = LogcatRecord {
+        pid: 1904,
+        tid: 6916,
+        level: crate::record::Level::Debug,
+        tag: "NetworkMonitor/139".to_string(),
+        process_name: "system_server".to_string(),
+        message: "PROBE_DNS \"connect\" 27ms\nOK".to_string(),
+        ..LogcatRecord::default()
+    };
+    assert_eq!(
+        logfmt(&record),
+        r#"pid=1904 tid=6916 level=D tag=NetworkMonitor/139 process_name=system_server message="PROBE_DNS \"connect\" 27ms\nOK""#
+    );
+}
+
+#[test]
+fn json_record() {
+    let record = LogcatRecord {
+        pid: 1904,
+        tid: 6916,
+        level: crate::record::Level::Debug,
+        tag: "NetworkMonitor".to_string(),
+        message: "OK".to_string(),
+        ..LogcatRecord::default()
+    };
+    assert_eq!(
+        serde_json::to_string(&record).unwrap(),
+        r#"{"timestamp":null,"pid":1904,"tid":6916,"uid":null,"level":"D","tag":"NetworkMonitor","process_name":"","message":"OK"}"#
+    );
+}
@@ -1,14 +1,178 @@
-use crate::record::{Level, LogcatRecord, ProcessRecord};
-use chrono::{prelude::*, LocalResult};
-use nom::bytes::complete::{tag, take, take_until1};
-use nom::character::complete::{char, i32, multispace1, u32};
+use crate::record::{Level, LogBuffer, LogcatRecord, ProcessEvent, ProcessRecord};
+use chrono::prelude::*;
+use clap::ValueEnum;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take, take_till1, take_until, take_until1, take_while_m_n};
+use nom::character::complete::{
+    alpha1, char, digit1, i32, i64, multispace1, one_of, space0, space1, u32,
+};
 
-use nom::combinator::{opt, peek, rest};
+use nom::combinator::{eof, opt, peek, recognize, rest};
 use nom::error::{Error, ErrorKind};
-use nom::sequence::{terminated, tuple};
+use nom::sequence::{delimited, preceded, terminated, tuple};
 use nom::{IResult, Parser};
 
-pub struct LogcatParser {}
+// `logcat -v` output formats, the `epoch`, `monotonic`, `uid`, `usec`, `nsec`,
+// `zone` and `year` modifiers are recognized in any of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Auto,
+    Brief,
+    Process,
+    Tag,
+    Thread,
+    Time,
+    Threadtime,
+    Long,
+}
+
+#[derive(Debug, Default)]
+pub struct LogcatParser {
+    pub format: LogFormat,
+    pub clock: DeviceClock,
+    // `long` entries span several lines, the header waits here for its message
+    pending: Option<LogcatRecord>,
+    // Buffer named by the last `--------- beginning of` divider
+    buffer: Option<LogBuffer>,
+    // Without `-D` logcat only marks the first entry of each buffer, so once a
+    // second buffer shows up entries aren't labeled until a `switch to` divider
+    // shows `-D` was used
+    switches: bool,
+    mixed: bool,
+}
+
+// A timestamp as printed, the year and zone may still have to be inferred
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LogTime {
+    Wall {
+        year: Option<i32>,
+        month: u32,
+        day: u32,
+        time: NaiveTime,
+        // From `-v zone`
+        zone: Option<FixedOffset>,
+    },
+    // `-v epoch`
+    Instant(DateTime<Local>),
+}
+
+// Settles what `MM-DD HH:MM:SS` timestamps leave out: the year, and the zone
+// the device wrote them in
+#[derive(Clone, Debug, Default)]
+pub struct DeviceClock {
+    // The device's UTC offset, the host's zone when unknown
+    pub zone: Option<FixedOffset>,
+    // The time the log was written up to: the device's clock when reading
+    // live, the file's modification time when replaying, now otherwise
+    pub reference: Option<DateTime<FixedOffset>>,
+    // Year and month of the last yearless timestamp
+    last: Option<(i32, u32)>,
+}
+
+impl DeviceClock {
+    // Nothing is logged after the reference date, so later days are from the
+    // year before. After that the year only moves forward, when the month
+    // wraps from December to January.
+    fn infer_year(&mut self, month: u32, day: u32) -> i32 {
+        let (year, last_month) = match self.last {
+            Some(last) => last,
+            None => {
+                let reference = self.now().date_naive();
+                let year = match NaiveDate::from_ymd_opt(reference.year(), month, day) {
+                    Some(date) if date <= reference => reference.year(),
+                    _ => reference.year() - 1,
+                };
+                (year, month)
+            }
+        };
+        let year = if last_month >= month + 6 {
+            year + 1
+        } else if month >= last_month + 6 {
+            // Written just before the rollover but read after it
+            return year - 1;
+        } else {
+            year
+        };
+        self.last = Some((year, month));
+        year
+    }
+    // What `--since 10m` or `--until 18:10` are relative to, in the device's zone
+    pub fn now(&self) -> DateTime<FixedOffset> {
+        let now = self
+            .reference
+            .unwrap_or_else(|| Local::now().fixed_offset());
+        match self.zone {
+            Some(zone) => now.with_timezone(&zone),
+            None => now,
+        }
+    }
+    // As `logcat -T` expects it, in the device's zone
+    pub fn format(&self, time: DateTime<Local>) -> String {
+        const FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+        match self.zone {
+            Some(zone) => time.with_timezone(&zone).format(FORMAT).to_string(),
+            None => time.format(FORMAT).to_string(),
+        }
+    }
+    fn resolve(&mut self, time: LogTime) -> Option<DateTime<Local>> {
+        let (year, month, day, time, zone) = match time {
+            LogTime::Instant(time) => return Some(time),
+            LogTime::Wall {
+                year,
+                month,
+                day,
+                time,
+                zone,
+            } => (year, month, day, time, zone),
+        };
+        let year = year.unwrap_or_else(|| self.infer_year(month, day));
+        let naive = NaiveDate::from_ymd_opt(year, month, day)?.and_time(time);
+        match zone.or(self.zone) {
+            Some(offset) => offset
+                .from_local_datetime(&naive)
+                .single()
+                .map(|t| t.with_timezone(&Local)),
+            None => Local.from_local_datetime(&naive).earliest(),
+        }
+    }
+}
+
+// `+0800`, `+08:00`, `-05`, `UTC` or `Z`
+pub fn parse_utc_offset(s: &str) -> Option<FixedOffset> {
+    let s = s.trim();
+    if matches!(s, "UTC" | "utc" | "Z" | "GMT") {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, digits) = match s.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    let digits = digits.replace(':', "");
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (
+            digits[..2].parse::<i32>().ok()?,
+            digits[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+pub const DEVICE_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+// Output of `date` with DEVICE_DATE_FORMAT on the device
+pub fn parse_device_date(output: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_str(output.trim(), DEVICE_DATE_FORMAT).ok()
+}
 fn parse_year(s: &str) -> IResult<&str, i32> {
     let (_line, i2) = peek(take::<usize, &str, Error<_>>(4usize))(s)?;
 
@@ -17,62 +181,446 @@ fn parse_year(s: &str) -> IResult<&str, i32> {
     let (line, _) = char('-')(line)?;
     Ok((line, value))
 }
-fn parse_timestamp(s: &str) -> IResult<&str, DateTime<Local>> {
+// .566 (default), .566123 (usec) or .566123456 (nsec) as nanoseconds
+fn parse_fraction(s: &str) -> IResult<&str, u32> {
+    let (s, digits) = preceded(char('.'), digit1)(s)?;
+    let nanos = digits
+        .chars()
+        .chain(std::iter::repeat('0'))
+        .take(9)
+        .fold(0, |n, c| n * 10 + c.to_digit(10).unwrap_or(0));
+    Ok((s, nanos))
+}
+// +0800 from `-v zone`, named zones other than UTC are accepted but ignored
+fn parse_zone(s: &str) -> IResult<&str, Option<FixedOffset>> {
+    let offset = tuple((
+        one_of("+-"),
+        take_while_m_n(4, 4, |c: char| c.is_ascii_digit()),
+    ))
+    .map(|(sign, hhmm): (char, &str)| {
+        let hhmm: i32 = hhmm.parse().unwrap_or(0);
+        let seconds = (hhmm / 100 * 3600 + hhmm % 100 * 60) * if sign == '-' { -1 } else { 1 };
+        FixedOffset::east_opt(seconds)
+    });
+    let name = terminated(
+        take_while_m_n(3, 5, |c: char| c.is_ascii_uppercase()),
+        peek(space1),
+    )
+    .map(|name| match name {
+        "UTC" | "GMT" => FixedOffset::east_opt(0),
+        _ => None,
+    });
+    preceded(space1, alt((offset, name)))(s)
+}
+fn parse_timestamp(s: &str) -> IResult<&str, LogTime> {
     // 08-30 18:10:53.566
     //or
     // 2017-08-30 18:10:53.566
-    // let (s,time_str) = terminated(take_until1("  "),tag("  "))(s)?;
     let (s, year) = opt(parse_year)(s)?;
-    let year = year.unwrap_or(Local::now().year());
     let (s, month) = terminated(u32, tag("-"))(s)?;
     let (s, day) = terminated(u32, tag(" "))(s)?;
     let (s, hour) = terminated(u32, tag(":"))(s)?;
     let (s, minute) = terminated(u32, tag(":"))(s)?;
-    let (s, second) = terminated(u32, tag("."))(s)?;
-    let (s, _millisecond) = terminated(u32, multispace1)(s)?;
-    let time = Local.with_ymd_and_hms(year, month, day, hour, minute, second);
-    match time {
-        LocalResult::None => Err(nom::Err::Error(Error::new(s, ErrorKind::Eof))),
-        LocalResult::Single(t) => {
-            let time = t;
-            Ok((s, time))
-        }
-        LocalResult::Ambiguous(t1, _t2) => {
-            let time = t1;
-            Ok((s, time))
+    let (s, second) = u32(s)?;
+    let (s, nanos) = parse_fraction(s)?;
+    let (s, zone) = opt(parse_zone)(s)?;
+    // 2000 is a leap year, so Feb 29 passes until the year is known
+    let Some(time) = NaiveTime::from_hms_nano_opt(hour, minute, second, nanos)
+        .filter(|_| NaiveDate::from_ymd_opt(year.unwrap_or(2000), month, day).is_some())
+    else {
+        return Err(nom::Err::Error(Error::new(s, ErrorKind::Verify)));
+    };
+    Ok((
+        s,
+        LogTime::Wall {
+            year,
+            month,
+            day,
+            time,
+            zone: zone.flatten(),
+        },
+    ))
+}
+// `-v epoch` prints seconds since 1970, `-v monotonic` seconds since boot.
+// Monotonic time can't be placed on the calendar and is dropped.
+fn parse_seconds(s: &str) -> IResult<&str, Option<DateTime<Local>>> {
+    let (s, (seconds, nanos)) = tuple((preceded(space0, i64), parse_fraction))(s)?;
+    // Anything before 1973 is taken as uptime
+    let time = (seconds > 100_000_000)
+        .then(|| Local.timestamp_opt(seconds, nanos).single())
+        .flatten();
+    Ok((s, time))
+}
+fn parse_time(s: &str) -> IResult<&str, Option<LogTime>> {
+    alt((
+        parse_timestamp.map(Some),
+        parse_seconds.map(|t| t.map(LogTime::Instant)),
+    ))(s)
+}
+fn parse_level(s: &str) -> IResult<&str, Level> {
+    one_of("TVDIWEFA")
+        .map(|c| Level::from(c.to_string().as_str()))
+        .parse(s)
+}
+// `-v uid` prints either the number or the name of the uid
+fn parse_uid(uid: &str) -> Option<u32> {
+    let uid = uid.trim().trim_end_matches(':');
+    if let Ok(uid) = uid.parse() {
+        return Some(uid);
+    }
+    match uid {
+        "root" => Some(0),
+        "system" => Some(1000),
+        "radio" => Some(1001),
+        "bluetooth" => Some(1002),
+        "wifi" => Some(1010),
+        "media" => Some(1013),
+        "nfc" => Some(1027),
+        "shell" => Some(2000),
+        "nobody" => Some(9999),
+        // u0_a153 is app 153 of user 0, u0_i5 isolated process 5
+        _ => {
+            let (user, id) = uid.strip_prefix('u')?.split_once('_')?;
+            let base = match id.get(..1)? {
+                "a" => 10_000,
+                "i" => 99_000,
+                _ => return None,
+            };
+            Some(user.parse::<u32>().ok()? * 100_000 + base + id[1..].parse::<u32>().ok()?)
         }
     }
 }
+// ( 1904) or (u0_a153: 1904) as (uid, pid)
+fn parse_paren_pid(s: &str) -> Option<(Option<u32>, u32)> {
+    match s
+        .trim()
+        .rsplit_once(|c: char| c == ':' || c.is_whitespace())
+    {
+        Some((uid, pid)) if !uid.trim().is_empty() => Some((parse_uid(uid), pid.parse().ok()?)),
+        _ => Some((None, s.trim().parse().ok()?)),
+    }
+}
 impl LogcatParser {
-    // use nom to parse logcat output
-    //08-30 18:10:53.566  1904  6916 D NetworkMonitor/139: PROBE_DNS connect.rom.miui.com 27ms OK 111.13.141.125,39.156.150.112,39.156.150.3,111.13.141.31
-    pub fn try_parse(&self, line: &str) -> Option<LogcatRecord> {
-        let (_s, (timestamp, pid, tid, level, tag, message)) = tuple((
-            parse_timestamp,
+    pub fn new(format: LogFormat) -> Self {
+        LogcatParser {
+            format,
+            ..LogcatParser::default()
+        }
+    }
+    // [uid]  pid   tid L tag: message
+    // 08-30 18:10:53.566  1904  6916 D NetworkMonitor/139: PROBE_DNS connect.rom.miui.com 27ms OK 111.13.141.125,39.156.150.112,39.156.150.3,111.13.141.31
+    fn parse_threadtime_body(s: &str) -> IResult<&str, LogcatRecord> {
+        let (s, uid) = opt(terminated(
+            take_till1(|c: char| c.is_whitespace()),
+            // only a uid if pid, tid and level follow
+            peek(tuple((
+                multispace1,
+                u32,
+                multispace1,
+                u32,
+                multispace1,
+                parse_level,
+            ))),
+        ))(s)?;
+        let (s, (pid, tid, level, tag, message)) = tuple((
             terminated(u32, multispace1),
             terminated(u32, multispace1),
-            terminated(take(1usize), multispace1).map(Level::from),
+            terminated(parse_level, multispace1),
             terminated(take_until1(":"), tag(": ")),
             // take_until eof
             rest,
-        ))(line)
-        .ok()?;
-        // println!("s:{:?},timestamp:{:?},pid:{:?},tid:{:?},level:{:?},tag:{:?},message:{:?}",s,timestamp,pid,tid,level,tag,message);
+        ))(uid.map_or(s, |_| s.trim_start()))?;
+        Ok((
+            s,
+            LogcatRecord {
+                pid,
+                tid,
+                uid: uid.and_then(parse_uid),
+                level,
+                tag: tag.trim_end().to_string(),
+                message: message.to_string(),
+                ..LogcatRecord::default()
+            },
+        ))
+    }
+    // D/NetworkMonitor/139( 1904): PROBE_DNS connect.rom.miui.com 27ms OK
+    fn parse_brief_body(s: &str) -> IResult<&str, LogcatRecord> {
+        let (s, (level, tag_pid, message)) = tuple((
+            terminated(parse_level, char('/')),
+            terminated(take_until1("): "), tag("): ")),
+            rest,
+        ))(s)?;
+        let Some(((uid, pid), tag)) = tag_pid
+            .rsplit_once('(')
+            .and_then(|(tag, pid)| Some((parse_paren_pid(pid)?, tag)))
+        else {
+            return Err(nom::Err::Error(Error::new(s, ErrorKind::Verify)));
+        };
+        Ok((
+            s,
+            LogcatRecord {
+                pid,
+                uid,
+                level,
+                tag: tag.trim_end().to_string(),
+                message: message.to_string(),
+                ..LogcatRecord::default()
+            },
+        ))
+    }
+    // D/NetworkMonitor/139: PROBE_DNS connect.rom.miui.com 27ms OK
+    fn parse_tag_body(s: &str) -> IResult<&str, LogcatRecord> {
+        let (s, (level, tag, message)) = tuple((
+            terminated(parse_level, char('/')),
+            terminated(take_until1(": "), tag(": ")),
+            rest,
+        ))(s)?;
+        Ok((
+            s,
+            LogcatRecord {
+                level,
+                tag: tag.trim_end().to_string(),
+                message: message.to_string(),
+                ..LogcatRecord::default()
+            },
+        ))
+    }
+    // D( 1904: 6916) PROBE_DNS connect.rom.miui.com 27ms OK
+    fn parse_thread_body(s: &str) -> IResult<&str, LogcatRecord> {
+        let (s, (level, pid, tid, message)) = tuple((
+            terminated(parse_level, char('(')),
+            terminated(take_until1(":"), char(':')),
+            terminated(preceded(space0, u32), tag(") ")),
+            rest,
+        ))(s)?;
+        let Some((uid, pid)) = parse_paren_pid(pid) else {
+            return Err(nom::Err::Error(Error::new(s, ErrorKind::Verify)));
+        };
+        Ok((
+            s,
+            LogcatRecord {
+                pid,
+                tid,
+                uid,
+                level,
+                message: message.to_string(),
+                ..LogcatRecord::default()
+            },
+        ))
+    }
+    // D( 1904) PROBE_DNS connect.rom.miui.com 27ms OK  (NetworkMonitor/139)
+    fn parse_process_body(s: &str) -> IResult<&str, LogcatRecord> {
+        let (s, (level, pid, message)) = tuple((
+            terminated(parse_level, char('(')),
+            terminated(take_until1(") "), tag(") ")),
+            rest,
+        ))(s)?;
+        let Some(((uid, pid), (message, tag))) =
+            parse_paren_pid(pid).zip(message.strip_suffix(')').and_then(|m| m.rsplit_once("  (")))
+        else {
+            return Err(nom::Err::Error(Error::new(s, ErrorKind::Verify)));
+        };
+        Ok((
+            s,
+            LogcatRecord {
+                pid,
+                uid,
+                level,
+                tag: tag.to_string(),
+                message: message.to_string(),
+                ..LogcatRecord::default()
+            },
+        ))
+    }
+    // [ 08-30 18:10:53.566  1904: 6916 D/NetworkMonitor/139 ]
+    fn parse_long_header(s: &str) -> IResult<&str, (Option<LogTime>, LogcatRecord)> {
+        let (s, (timestamp, pid, tid, level, tag)) = tuple((
+            delimited(tag("[ "), parse_time, multispace1),
+            terminated(take_until1(":"), char(':')),
+            delimited(space0, u32, space1),
+            terminated(parse_level, char('/')),
+            terminated(take_until1(" ]"), tuple((tag(" ]"), eof))),
+        ))(s)?;
+        let Some((uid, pid)) = parse_paren_pid(pid) else {
+            return Err(nom::Err::Error(Error::new(s, ErrorKind::Verify)));
+        };
+        Ok((
+            s,
+            (
+                timestamp,
+                LogcatRecord {
+                    pid,
+                    tid,
+                    uid,
+                    level,
+                    tag: tag.trim_end().to_string(),
+                    ..LogcatRecord::default()
+                },
+            ),
+        ))
+    }
+    fn parse_single_line(&mut self, line: &str) -> Option<LogcatRecord> {
+        let timed = |body: fn(&str) -> IResult<&str, LogcatRecord>| {
+            move |s| tuple((terminated(parse_time, multispace1), body))(s)
+        };
+        let untimed = |body: fn(&str) -> IResult<&str, LogcatRecord>| {
+            move |s| body(s).map(|(s, record)| (s, (None, record)))
+        };
+        let result = match self.format {
+            LogFormat::Brief => untimed(Self::parse_brief_body)(line),
+            LogFormat::Process => untimed(Self::parse_process_body)(line),
+            LogFormat::Tag => untimed(Self::parse_tag_body)(line),
+            LogFormat::Thread => untimed(Self::parse_thread_body)(line),
+            LogFormat::Time => timed(Self::parse_brief_body)(line),
+            LogFormat::Threadtime => timed(Self::parse_threadtime_body)(line),
+            LogFormat::Long => return None,
+            LogFormat::Auto => alt((
+                timed(Self::parse_threadtime_body),
+                timed(Self::parse_brief_body),
+                untimed(Self::parse_brief_body),
+                untimed(Self::parse_thread_body),
+                untimed(Self::parse_process_body),
+                untimed(Self::parse_tag_body),
+            ))(line),
+        };
+        let (_s, (timestamp, record)) = result.ok()?;
         Some(LogcatRecord {
-            timestamp: Some(timestamp),
-            pid,
+            timestamp: timestamp.and_then(|t| self.clock.resolve(t)),
             raw: line.to_string(),
-            tag: tag.to_string(),
-            message: message.to_string(),
-            tid,
-            level,
-            ..LogcatRecord::default()
+            buffer: self.buffer,
+            ..record
         })
     }
+    // Whether the last line was swallowed by a `long` entry that isn't finished yet
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+    // The `long` entry still waiting for its blank line
+    pub fn flush(&mut self) -> Option<LogcatRecord> {
+        self.pending.take()
+    }
+    // --------- beginning of main
+    // --------- switch to system
+    pub fn parse_divider(line: &str) -> Option<&str> {
+        let (_s, divider) = preceded(
+            tuple((tag("---------"), space1::<&str, Error<_>>)),
+            alt((
+                recognize(tuple((tag("beginning of "), alpha1))),
+                recognize(tuple((tag("switch to "), alpha1))),
+            )),
+        )(line)
+        .ok()?;
+        Some(divider)
+    }
+    // use nom to parse logcat output
+    pub fn try_parse(&mut self, line: &str) -> Option<LogcatRecord> {
+        if let Some(pending) = &mut self.pending {
+            // A `long` entry ends with an empty line
+            if line.is_empty() {
+                return self.pending.take();
+            }
+            if !pending.message.is_empty() {
+                pending.message.push('\n');
+            }
+            pending.message.push_str(line);
+            pending.raw.push('\n');
+            pending.raw.push_str(line);
+            return None;
+        }
+        if matches!(self.format, LogFormat::Auto | LogFormat::Long) {
+            if let Ok((_s, (timestamp, record))) = Self::parse_long_header(line) {
+                self.pending = Some(LogcatRecord {
+                    timestamp: timestamp.and_then(|t| self.clock.resolve(t)),
+                    raw: line.to_string(),
+                    buffer: self.buffer,
+                    ..record
+                });
+                return None;
+            }
+        }
+        if let Some(divider) = Self::parse_divider(line) {
+            let buffer = divider
+                .rsplit(' ')
+                .next()
+                .and_then(|name| LogBuffer::from_str(name, true).ok());
+            self.switches |= divider.starts_with("switch to");
+            if self.buffer.is_some_and(|b| Some(b) != buffer) {
+                self.mixed = true;
+            }
+            self.buffer = match self.mixed && !self.switches {
+                true => None,
+                false => buffer,
+            };
+            return None;
+        }
+        self.parse_single_line(line)
+    }
 }
 
+// Columns asked from toybox `ps -o`, NAME goes last as it may contain spaces
+pub const PS_COLUMNS: &str = "PID,PPID,UID,USER,RSS,S,ETIME,NAME";
+
 pub struct PSParser {}
 impl PSParser {
+    // The header of `ps -o` output starts with PID, toolbox and default toybox
+    // listings start with USER and are read by `try_parse`
+    pub fn columns(header: &str) -> Option<Vec<&str>> {
+        let columns: Vec<&str> = header.split_whitespace().collect();
+        match columns.first() {
+            Some(&"PID") => Some(columns),
+            _ => None,
+        }
+    }
+    // `[[DD-]HH:]MM:SS` since the process started
+    fn parse_elapsed(s: &str) -> Option<i64> {
+        let (days, s) = match s.split_once('-') {
+            Some((days, s)) => (days.parse::<i64>().ok()?, s),
+            None => (0, s),
+        };
+        let seconds = s.split(':').try_fold(0, |total, part| {
+            Some(total * 60 + part.parse::<i64>().ok()?)
+        })?;
+        Some(days * 86400 + seconds)
+    }
+    //  PID  PPID   UID USER            RSS S     ELAPSED NAME
+    // 24103   772 10153 u0_a153     232896 S       05:12 com.google.android.GoogleCamera
+    pub fn try_parse_columns(
+        &self,
+        columns: &[&str],
+        line: &str,
+        now: DateTime<Local>,
+    ) -> Option<ProcessRecord> {
+        let mut record = ProcessRecord::default();
+        let mut s = line.trim();
+        for (i, column) in columns.iter().enumerate() {
+            let value = match i + 1 == columns.len() {
+                true => std::mem::take(&mut s),
+                false => {
+                    let (value, tail) = s.split_once(char::is_whitespace)?;
+                    s = tail.trim_start();
+                    value
+                }
+            };
+            match *column {
+                "PID" => record.pid = value.parse().ok()?,
+                "PPID" => record.ppid = value.parse().ok()?,
+                "UID" => record.uid = value.parse().ok(),
+                "USER" => record.user = value.to_string(),
+                "RSS" => record.rss = value.parse().ok()?,
+                "S" => record.pc = value.to_string(),
+                "ELAPSED" | "ETIME" => {
+                    record.started = Self::parse_elapsed(value)
+                        .map(|elapsed| now - chrono::Duration::seconds(elapsed))
+                }
+                "NAME" | "CMD" | "CMDLINE" | "COMM" => record.name = value.to_string(),
+                _ => {}
+            }
+        }
+        match record.name.is_empty() {
+            true => None,
+            false => Some(record),
+        }
+    }
     // use nom to parse ps output
     //user          pid  ppid  vsize  rss   wchan            pc  name
     // u0_a153      24103   772 16935184 232896 0                  0 S com.google.android.GoogleCamera
@@ -97,14 +645,92 @@ impl PSParser {
             rss,
             pc: pc.to_string(),
             name: name.to_string(),
+            uid: parse_uid(user),
             ..ProcessRecord::default()
         })
     }
 }
+pub struct ActivityManagerParser {}
+impl ActivityManagerParser {
+    // Start proc 12345:com.example.app/u0a123 for activity {com.example.app/.MainActivity}
+    fn parse_start(s: &str) -> IResult<&str, ProcessEvent> {
+        let (s, (pid, name)) = preceded(
+            tag("Start proc "),
+            tuple((
+                terminated(u32, char(':')),
+                take_till1(|c| c == '/' || c == ' '),
+            )),
+        )(s)?;
+        Ok((
+            s,
+            ProcessEvent::Started {
+                pid,
+                name: name.to_string(),
+            },
+        ))
+    }
+    // Start proc com.example.app for activity com.example.app/.MainActivity: pid=12345 uid=10123 gids={50123}
+    fn parse_start_legacy(s: &str) -> IResult<&str, ProcessEvent> {
+        let (s, name) = preceded(tag("Start proc "), take_till1(|c| c == ' '))(s)?;
+        let (s, pid) = preceded(tuple((take_until("pid="), tag("pid="))), u32)(s)?;
+        Ok((
+            s,
+            ProcessEvent::Started {
+                pid,
+                name: name.to_string(),
+            },
+        ))
+    }
+    // Process com.example.app (pid 12345) has died: fore TOP
+    fn parse_died(s: &str) -> IResult<&str, ProcessEvent> {
+        let (s, (name, pid)) = tuple((
+            preceded(tag("Process "), take_until1(" (pid ")),
+            terminated(preceded(tag(" (pid "), u32), tag(") has died")),
+        ))(s)?;
+        Ok((
+            s,
+            ProcessEvent::Died {
+                pid,
+                name: name.to_string(),
+            },
+        ))
+    }
+    // Killing 12345:com.example.app/u0a123 (adj 900): empty #17
+    fn parse_killed(s: &str) -> IResult<&str, ProcessEvent> {
+        let (s, (pid, name)) = preceded(
+            tag("Killing "),
+            tuple((
+                terminated(u32, char(':')),
+                take_till1(|c| c == '/' || c == ' '),
+            )),
+        )(s)?;
+        Ok((
+            s,
+            ProcessEvent::Died {
+                pid,
+                name: name.to_string(),
+            },
+        ))
+    }
+    pub fn try_parse(&self, record: &LogcatRecord) -> Option<ProcessEvent> {
+        if record.tag != "ActivityManager" {
+            return None;
+        }
+        let (_s, event) = alt((
+            Self::parse_start,
+            Self::parse_start_legacy,
+            Self::parse_died,
+            Self::parse_killed,
+        ))(record.message.as_str())
+        .ok()?;
+        Some(event)
+    }
+}
+
 #[test]
 fn parse_logcat_line() {
     let line = "08-30 18:10:53.566  1904  6916 D NetworkMonitor/139: PROBE_DNS connect.rom.miui.com 27ms OK";
-    let res = LogcatParser {}.try_parse(line).unwrap();
+    let res = LogcatParser::default().try_parse(line).unwrap();
     println!("{:?}", res);
     assert_eq!(res.timestamp.unwrap().day(), 30);
     assert_eq!(res.timestamp.unwrap().hour(), 18);
@@ -120,7 +746,7 @@ fn parse_logcat_line() {
 #[test]
 fn parse_logcat_line_y() {
     let line = "2018-08-30 18:10:53.566  1904  6916 D NetworkMonitor/139: PROBE_DNS connect.rom.miui.com 27ms OK";
-    let res = LogcatParser {}.try_parse(line).unwrap();
+    let res = LogcatParser::default().try_parse(line).unwrap();
     println!("{:?}", res);
     assert_eq!(res.timestamp.unwrap().day(), 30);
     assert_eq!(res.timestamp.unwrap().hour(), 18);
@@ -134,6 +760,287 @@ fn parse_logcat_line_y() {
     assert_eq!(res.message, "PROBE_DNS connect.rom.miui.com 27ms OK");
 }
 
+#[cfg(test)]
+fn parse_as(format: LogFormat, line: &str) -> LogcatRecord {
+    // Auto-detection has to agree with the explicit format
+    let res = LogcatParser::new(format).try_parse(line);
+    assert_eq!(res, LogcatParser::default().try_parse(line));
+    res.unwrap()
+}
+#[test]
+fn parse_brief_line() {
+    let res = parse_as(
+        LogFormat::Brief,
+        "D/NetworkMonitor/139( 1904): PROBE_DNS connect.rom.miui.com 27ms OK",
+    );
+    assert_eq!(res.timestamp, None);
+    assert_eq!(res.pid, 1904);
+    assert_eq!(res.level, Level::Debug);
+    assert_eq!(res.tag, "NetworkMonitor/139");
+    assert_eq!(res.message, "PROBE_DNS connect.rom.miui.com 27ms OK");
+}
+#[test]
+fn parse_process_line() {
+    let res = parse_as(
+        LogFormat::Process,
+        "D( 1904) PROBE_DNS connect.rom.miui.com 27ms OK  (NetworkMonitor/139)",
+    );
+    assert_eq!(res.pid, 1904);
+    assert_eq!(res.level, Level::Debug);
+    assert_eq!(res.tag, "NetworkMonitor/139");
+    assert_eq!(res.message, "PROBE_DNS connect.rom.miui.com 27ms OK");
+}
+#[test]
+fn parse_tag_line() {
+    let res = parse_as(LogFormat::Tag, "W/ActivityManager: Slow operation");
+    assert_eq!(res.level, Level::Warn);
+    assert_eq!(res.tag, "ActivityManager");
+    assert_eq!(res.message, "Slow operation");
+}
+#[test]
+fn parse_thread_line() {
+    let res = parse_as(LogFormat::Thread, "I( 1904: 6916) Slow operation");
+    assert_eq!(res.pid, 1904);
+    assert_eq!(res.tid, 6916);
+    assert_eq!(res.level, Level::Info);
+    assert_eq!(res.message, "Slow operation");
+}
+#[test]
+fn parse_time_line() {
+    let res = parse_as(
+        LogFormat::Time,
+        "08-30 18:10:53.566 D/NetworkMonitor/139( 1904): PROBE_DNS connect.rom.miui.com 27ms OK",
+    );
+    assert_eq!(res.timestamp.unwrap().day(), 30);
+    assert_eq!(res.timestamp.unwrap().second(), 53);
+    assert_eq!(res.timestamp.unwrap().nanosecond(), 566_000_000);
+    assert_eq!(res.pid, 1904);
+    assert_eq!(res.tag, "NetworkMonitor/139");
+    assert_eq!(res.message, "PROBE_DNS connect.rom.miui.com 27ms OK");
+}
+#[test]
+fn parse_long_lines() {
+    let mut parser = LogcatParser::default();
+    assert_eq!(
+        parser.try_parse("[ 08-30 18:10:53.566  1904: 6916 D/NetworkMonitor/139 ]"),
+        None
+    );
+    assert_eq!(parser.try_parse("PROBE_DNS connect.rom.miui.com"), None);
+    assert_eq!(parser.try_parse("27ms OK"), None);
+    let res = parser.try_parse("").unwrap();
+    assert_eq!(res.timestamp.unwrap().minute(), 10);
+    assert_eq!(res.pid, 1904);
+    assert_eq!(res.tid, 6916);
+    assert_eq!(res.level, Level::Debug);
+    assert_eq!(res.tag, "NetworkMonitor/139");
+    assert_eq!(res.message, "PROBE_DNS connect.rom.miui.com\n27ms OK");
+    // The last entry of a file without a trailing blank line
+    assert_eq!(
+        parser.try_parse("[ 08-30 18:10:54.000  1904: 6916 I/NetworkMonitor ]"),
+        None
+    );
+    assert_eq!(parser.try_parse("PROBE_HTTP OK"), None);
+    assert_eq!(parser.flush().unwrap().message, "PROBE_HTTP OK");
+    assert_eq!(parser.flush(), None);
+}
+#[test]
+fn parse_timestamp_modifiers() {
+    let usec = parse_as(
+        LogFormat::Threadtime,
+        "08-30 18:10:53.566123  1904  6916 D NetworkMonitor: OK",
+    );
+    assert_eq!(usec.timestamp.unwrap().nanosecond(), 566_123_000);
+    let nsec = parse_as(
+        LogFormat::Threadtime,
+        "08-30 18:10:53.566123456  1904  6916 D NetworkMonitor: OK",
+    );
+    assert_eq!(nsec.timestamp.unwrap().nanosecond(), 566_123_456);
+    let zone = parse_as(
+        LogFormat::Threadtime,
+        "2018-08-30 18:10:53.566 +0000  1904  6916 D NetworkMonitor: OK",
+    );
+    assert_eq!(
+        zone.timestamp.unwrap().with_timezone(&Utc),
+        Utc.with_ymd_and_hms(2018, 8, 30, 18, 10, 53).unwrap()
+            + chrono::Duration::milliseconds(566)
+    );
+    let epoch = parse_as(
+        LogFormat::Threadtime,
+        "1535652653.566  1904  6916 D NetworkMonitor: OK",
+    );
+    assert_eq!(epoch.timestamp.unwrap().timestamp(), 1535652653);
+    let monotonic = parse_as(LogFormat::Time, "  8193.566 D/NetworkMonitor( 1904): OK");
+    assert_eq!(monotonic.timestamp, None);
+    assert_eq!(monotonic.pid, 1904);
+}
+#[test]
+fn infer_timestamp_year() {
+    let utc = |line: &str, parser: &mut LogcatParser| {
+        let record = parser.try_parse(line).unwrap();
+        record.timestamp.unwrap().with_timezone(&Utc).to_rfc3339()
+    };
+    let device = |reference: (i32, u32, u32)| {
+        let mut parser = LogcatParser::new(LogFormat::Threadtime);
+        let zone = FixedOffset::east_opt(8 * 3600).unwrap();
+        parser.clock = DeviceClock {
+            zone: Some(zone),
+            reference: zone
+                .with_ymd_and_hms(reference.0, reference.1, reference.2, 12, 0, 0)
+                .single(),
+            ..DeviceClock::default()
+        };
+        parser
+    };
+    // Started on Dec 31, the year moves forward at midnight and stays there
+    let mut parser = device((2024, 12, 31));
+    let lines = [
+        (
+            "12-31 23:59:59.000  1904  6916 D A: OK",
+            "2024-12-31T15:59:59+00:00",
+        ),
+        (
+            "01-01 00:00:01.000  1904  6916 D A: OK",
+            "2024-12-31T16:00:01+00:00",
+        ),
+        // Logged before midnight, read after it
+        (
+            "12-31 23:59:59.500  1904  6916 D A: OK",
+            "2024-12-31T15:59:59.500+00:00",
+        ),
+        (
+            "01-01 00:00:02.000  1904  6916 D A: OK",
+            "2024-12-31T16:00:02+00:00",
+        ),
+        (
+            "03-01 00:00:00.000  1904  6916 D A: OK",
+            "2025-02-28T16:00:00+00:00",
+        ),
+    ];
+    for (line, expected) in lines {
+        assert_eq!(utc(line, &mut parser), expected);
+    }
+    // Started on Jan 1, the December backlog is from the year before
+    let mut parser = device((2025, 1, 1));
+    assert_eq!(
+        utc("12-31 23:59:59.000  1904  6916 D A: OK", &mut parser),
+        "2024-12-31T15:59:59+00:00"
+    );
+    assert_eq!(
+        utc("01-01 00:00:01.000  1904  6916 D A: OK", &mut parser),
+        "2024-12-31T16:00:01+00:00"
+    );
+    // A log saved in December replayed the next year
+    let mut parser = device((2024, 12, 31));
+    assert_eq!(
+        utc("12-30 10:00:00.000  1904  6916 D A: OK", &mut parser),
+        "2024-12-30T02:00:00+00:00"
+    );
+    // Explicit years and zones are kept
+    assert_eq!(
+        utc(
+            "2020-06-01 12:00:00.000 +0000  1904  6916 D A: OK",
+            &mut parser
+        ),
+        "2020-06-01T12:00:00+00:00"
+    );
+    assert!(parser
+        .try_parse("13-01 00:00:00.000  1904  6916 D A: OK")
+        .is_none());
+    assert_eq!(
+        parser
+            .clock
+            .format(Utc.with_ymd_and_hms(2024, 12, 31, 16, 0, 0).unwrap().into()),
+        "2025-01-01 00:00:00.000"
+    );
+}
+#[test]
+fn parse_offsets() {
+    let east = |seconds| FixedOffset::east_opt(seconds);
+    assert_eq!(parse_utc_offset("+0530"), east(5 * 3600 + 30 * 60));
+    assert_eq!(parse_utc_offset("-08:00"), east(-8 * 3600));
+    assert_eq!(parse_utc_offset("+9"), east(9 * 3600));
+    assert_eq!(parse_utc_offset("UTC"), east(0));
+    assert_eq!(parse_utc_offset("0800"), None);
+    assert_eq!(parse_utc_offset("+08:75"), None);
+    let date = parse_device_date("2024-12-31 23:59:58 -0500\n").unwrap();
+    assert_eq!(
+        date.date_naive(),
+        NaiveDate::from_ymd_opt(2024, 12, 31).unwrap()
+    );
+    assert_eq!(date.offset(), &east(-5 * 3600).unwrap());
+    assert_eq!(parse_device_date("date: not found"), None);
+}
+#[test]
+fn parse_uid_modifier() {
+    let res = parse_as(
+        LogFormat::Threadtime,
+        "08-30 18:10:53.566 u0_a153  1904  6916 D NetworkMonitor: OK",
+    );
+    assert_eq!(res.uid, Some(10153));
+    assert_eq!(res.pid, 1904);
+    assert_eq!(res.tid, 6916);
+    let res = parse_as(
+        LogFormat::Threadtime,
+        "08-30 18:10:53.566  1000  1904  6916 D NetworkMonitor: OK",
+    );
+    assert_eq!(res.uid, Some(1000));
+    assert_eq!(res.pid, 1904);
+    let res = parse_as(LogFormat::Brief, "D/NetworkMonitor(system: 1904): OK");
+    assert_eq!(res.uid, Some(1000));
+    assert_eq!(res.pid, 1904);
+}
+
+#[test]
+fn parse_divider_line() {
+    assert_eq!(
+        LogcatParser::parse_divider("--------- beginning of main"),
+        Some("beginning of main")
+    );
+    assert_eq!(
+        LogcatParser::parse_divider("--------- switch to crash"),
+        Some("switch to crash")
+    );
+    assert_eq!(LogcatParser::parse_divider("---------"), None);
+    let mut parser = LogcatParser::default();
+    assert_eq!(parser.try_parse("--------- beginning of crash"), None);
+    let res = parser
+        .try_parse("08-30 18:10:53.566  1904  1904 E AndroidRuntime: FATAL EXCEPTION: main")
+        .unwrap();
+    assert_eq!(res.buffer, Some(LogBuffer::Crash));
+
+    let line = "08-30 18:10:53.566  1904  1904 I ActivityManager: OK";
+    let buffers = |lines: &[&str]| {
+        let mut parser = LogcatParser::default();
+        lines
+            .iter()
+            .filter_map(|l| parser.try_parse(l))
+            .map(|r| r.buffer)
+            .collect::<Vec<_>>()
+    };
+    // Without `-D` the main entries after the first crash one aren't marked
+    assert_eq!(
+        buffers(&[
+            "--------- beginning of main",
+            line,
+            "--------- beginning of crash",
+            line,
+            line,
+        ]),
+        vec![Some(LogBuffer::Main), None, None]
+    );
+    assert_eq!(
+        buffers(&[
+            "--------- beginning of main",
+            line,
+            "--------- beginning of crash",
+            line,
+            "--------- switch to main",
+            line,
+        ]),
+        vec![Some(LogBuffer::Main), None, Some(LogBuffer::Main)]
+    );
+}
+
 #[test]
 fn parse_ps_line() {
     let line = "u0_a153      24103   772 16935184 232896 0                  0 S com.google.android.GoogleCamera";
@@ -145,4 +1052,84 @@ fn parse_ps_line() {
     assert_eq!(res.rss, 232896);
     assert_eq!(res.name, "com.google.android.GoogleCamera");
     assert_eq!(res.pc, "S");
+    assert_eq!(res.uid, Some(10153));
+}
+
+#[test]
+fn parse_toybox_ps_line() {
+    // `ps -A` on Android 8+, same shape as toolbox with VSZ and ADDR
+    let line = "u0_a153      24103   772 16935184 232896 0                   0 S com.google.android.GoogleCamera";
+    let res = PSParser {}.try_parse(line).unwrap();
+    assert_eq!(res.pid, 24103);
+    assert_eq!(res.name, "com.google.android.GoogleCamera");
+
+    // toybox names the ETIME column ELAPSED
+    let header = "  PID  PPID   UID USER            RSS S     ELAPSED NAME";
+    let columns = PSParser::columns(header).unwrap();
+    let line = "24103   772 10153 u0_a153     232896 S  1-02:03:04 com.google.android.GoogleCamera:remote service";
+    let now = Local::now();
+    let res = PSParser {}.try_parse_columns(&columns, line, now).unwrap();
+    assert_eq!(res.user, "u0_a153");
+    assert_eq!(res.pid, 24103);
+    assert_eq!(res.ppid, 772);
+    assert_eq!(res.uid, Some(10153));
+    assert_eq!(res.rss, 232896);
+    assert_eq!(res.pc, "S");
+    assert_eq!(res.name, "com.google.android.GoogleCamera:remote service");
+    assert_eq!((now - res.started.unwrap()).num_seconds(), 93784);
+    let line = "1904   772  1000 system      232896 S       00:07 system_server";
+    let res = PSParser {}.try_parse_columns(&columns, line, now).unwrap();
+    assert_eq!((now - res.started.unwrap()).num_seconds(), 7);
+    assert_eq!(
+        PSParser::columns("USER PID PPID VSZ RSS WCHAN ADDR S NAME"),
+        None
+    );
+}
+
+#[test]
+fn parse_activity_manager_line() {
+    let parser = ActivityManagerParser {};
+    let record = |message: &str| LogcatRecord {
+        tag: "ActivityManager".to_string(),
+        message: message.to_string(),
+        ..LogcatRecord::default()
+    };
+    assert_eq!(
+        parser.try_parse(&record(
+            "Start proc 24103:com.example.app:remote/u0a153 for service {com.example.app/.Sync}"
+        )),
+        Some(ProcessEvent::Started {
+            pid: 24103,
+            name: "com.example.app:remote".to_string()
+        })
+    );
+    assert_eq!(
+        parser.try_parse(&record("Start proc com.example.app for activity com.example.app/.Main: pid=24103 uid=10153 gids={50153}")),
+        Some(ProcessEvent::Started {
+            pid: 24103,
+            name: "com.example.app".to_string()
+        })
+    );
+    assert_eq!(
+        parser.try_parse(&record(
+            "Process com.example.app (pid 24103) has died: fore TOP"
+        )),
+        Some(ProcessEvent::Died {
+            pid: 24103,
+            name: "com.example.app".to_string()
+        })
+    );
+    assert_eq!(
+        parser.try_parse(&record(
+            "Killing 24103:com.example.app/u0a153 (adj 900): empty #17"
+        )),
+        Some(ProcessEvent::Died {
+            pid: 24103,
+            name: "com.example.app".to_string()
+        })
+    );
+    assert_eq!(
+        parser.try_parse(&record("Displayed com.example.app/.Main: +320ms")),
+        None
+    );
 }
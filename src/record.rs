@@ -1,11 +1,13 @@
+use crate::utils::in_zone;
 use chrono::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use std::fmt::Display;
+use std::time::Instant;
 
 // const LEVEL_VALUES: &[&str] = &[
 //     "trace", "debug", "info", "warn", "error", "fatal", "assert", "T", "D", "I", "W", "E", "F", "A",
 // ];
-#[derive(Clone, Debug, Deserialize, PartialOrd, PartialEq, Serialize, Default)]
+#[derive(Clone, Copy, Debug, PartialOrd, PartialEq, Default)]
 pub enum Level {
     #[default]
     None,
@@ -37,18 +39,25 @@ impl Display for Level {
         )
     }
 }
+// One letter, like the CSV and logfmt columns
+impl Serialize for Level {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
 
-impl<'a> From<&'a str> for Level {
+// Letters and full names, in any case
+impl From<&str> for Level {
     fn from(s: &str) -> Self {
-        match s {
-            "T" | "trace" => Level::Trace,
-            "V" | "verbose" => Level::Verbose,
-            "D" | "debug" => Level::Debug,
-            "I" | "info" => Level::Info,
-            "W" | "warn" => Level::Warn,
-            "E" | "error" => Level::Error,
-            "F" | "fatal" => Level::Fatal,
-            "A" | "assert" => Level::Assert,
+        match s.to_ascii_lowercase().as_str() {
+            "t" | "trace" => Level::Trace,
+            "v" | "verbose" => Level::Verbose,
+            "d" | "debug" => Level::Debug,
+            "i" | "info" => Level::Info,
+            "w" | "warn" => Level::Warn,
+            "e" | "error" => Level::Error,
+            "f" | "fatal" => Level::Fatal,
+            "a" | "assert" => Level::Assert,
             _ => Level::None,
         }
     }
@@ -59,16 +68,86 @@ impl<'a> From<&'a str> for Level {
 //         LEVEL_VALUES
 //     }
 // }
-#[derive(Debug, Clone, PartialEq, Default)]
+// `logcat -b` buffers
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogBuffer {
+    Main,
+    System,
+    Crash,
+    Radio,
+    Events,
+    Kernel,
+    Security,
+    Stats,
+    Default,
+    All,
+}
+impl Display for LogBuffer {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                LogBuffer::Main => "main",
+                LogBuffer::System => "system",
+                LogBuffer::Crash => "crash",
+                LogBuffer::Radio => "radio",
+                LogBuffer::Events => "events",
+                LogBuffer::Kernel => "kernel",
+                LogBuffer::Security => "security",
+                LogBuffer::Stats => "stats",
+                LogBuffer::Default => "default",
+                LogBuffer::All => "all",
+            }
+        )
+    }
+}
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
 pub struct LogcatRecord {
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub timestamp: Option<DateTime<Local>>,
+    pub pid: u32,
+    pub tid: u32,
+    pub uid: Option<u32>,
     pub level: Level,
     pub tag: String,
+    pub process_name: String,
+    // Resolved with `--show-thread`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_name: Option<String>,
+    // App owning the UID, from `pm list packages -U`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
     pub message: String,
-    pub pid: u32,
-    pub tid: u32,
+    // Serial of the device the line came from, set with `--all-devices`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    // Known when several buffers are read and logcat printed `beginning of` dividers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buffer: Option<LogBuffer>,
+    // Named values of an `events` buffer entry
+    #[serde(
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_fields"
+    )]
+    pub fields: Vec<(String, String)>,
+    #[serde(skip)]
     pub raw: String,
-    pub process_name: String,
-    pub timestamp: Option<DateTime<Local>>,
+}
+// In the `--utc` or `--tz` zone like the other outputs
+fn serialize_timestamp<S: Serializer>(
+    timestamp: &Option<DateTime<Local>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    timestamp.map(in_zone).serialize(serializer)
+}
+// Keeps the field order but serializes as an object
+fn serialize_fields<S: Serializer>(
+    fields: &[(String, String)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(fields.iter().map(|(k, v)| (k, v)))
 }
 impl Display for LogcatRecord {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
@@ -94,5 +173,27 @@ pub struct ProcessRecord {
     pub ppid: u32,
     pub name: String,
     pub rss: u32,
+    // Process state, e.g. `S`
     pub pc: String,
+    // Only listed by toybox `ps -o`
+    pub uid: Option<u32>,
+    // From `ps -o ETIME` or the ActivityManager line announcing it
+    pub started: Option<DateTime<Local>>,
+    // When it was found gone, kept a while for lines logged before that
+    pub died: Option<DateTime<Local>>,
+    // When this host learned about it, a `ps` poll started earlier can't know it
+    #[serde(skip)]
+    pub added: Option<Instant>,
+}
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProcessEvent {
+    Started { pid: u32, name: String },
+    Died { pid: u32, name: String },
+}
+impl ProcessEvent {
+    pub fn name(&self) -> &str {
+        match self {
+            ProcessEvent::Started { name, .. } | ProcessEvent::Died { name, .. } => name,
+        }
+    }
 }
@@ -0,0 +1,282 @@
+use crate::adb::{self, AdbClient};
+use crate::binary;
+use crate::parser::{DeviceClock, LogcatParser};
+use crate::record::LogcatRecord;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use process_stream::{stream, Stream, StreamExt};
+use std::path::PathBuf;
+use std::pin::Pin;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+
+// Where logcat lines come from
+#[derive(Clone, Debug)]
+pub enum Source {
+    Adb {
+        client: AdbClient,
+        // The logcat command run on the device
+        args: Vec<String>,
+        // `args` run `logcat -B`, entries are read as `logger_entry` structs
+        binary: bool,
+        // Passed as `-T`, saves dumping the whole buffer just to drop most of it
+        since: Option<DateTime<Local>>,
+    },
+    File {
+        path: PathBuf,
+        binary: bool,
+    },
+    Stdin {
+        binary: bool,
+    },
+}
+
+// Text sources yield lines for `LogcatParser`, binary ones complete records
+#[derive(Debug)]
+pub enum SourceItem {
+    Output(String),
+    Record(Box<LogcatRecord>),
+    Error(String),
+    Exit(String),
+    // No more lines for now, entries waiting for theirs are complete
+    Flush,
+}
+
+pub type SourceStream = Pin<Box<dyn Stream<Item = SourceItem> + Send>>;
+
+impl Source {
+    pub fn is_live(&self) -> bool {
+        matches!(self, Source::Adb { .. })
+    }
+    pub fn client(&self) -> Option<&AdbClient> {
+        match self {
+            Source::Adb { client, .. } => Some(client),
+            _ => None,
+        }
+    }
+    pub fn serial(&self) -> Option<&str> {
+        self.client().and_then(|c| c.serial.as_deref())
+    }
+    fn has_dividers(&self) -> bool {
+        matches!(self, Source::Adb { args, .. } if args.iter().any(|a| a == "-D"))
+    }
+    // Logcat before Android 7 doesn't know `-D`
+    fn drop_dividers(&mut self) -> bool {
+        let had = self.has_dividers();
+        if let Source::Adb { args, .. } = self {
+            args.retain(|a| a != "-D");
+        }
+        had
+    }
+    // Starts logcat once, `extra_args` are appended to the source's own
+    async fn spawn(&self, extra_args: &[String]) -> Result<SourceStream> {
+        let Source::Adb {
+            client,
+            args,
+            binary,
+            ..
+        } = self
+        else {
+            unreachable!("not an adb source")
+        };
+        let command = args
+            .iter()
+            .chain(extra_args)
+            .map(|arg| adb::shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        // `shell:` may translate line endings, `exec:` keeps entries intact
+        let stream = match binary {
+            true => client.open_async(&format!("exec:{}", command)).await?,
+            false => client.open_async(&format!("shell:{}", command)).await?,
+        };
+        let mut items = match binary {
+            true => read_entries(stream),
+            false => read_lines(stream),
+        };
+        // The server only closes the connection, the exit status isn't passed on
+        Ok(stream! {
+            while let Some(item) = items.next().await {
+                yield item;
+            }
+            yield SourceItem::Exit("connection closed".to_string());
+        }
+        .boxed())
+    }
+    // `clock` is the device's, `-T` takes its local time
+    pub async fn stream(&self, clock: &DeviceClock) -> Result<SourceStream> {
+        match self {
+            Source::Adb { since, .. } => {
+                let since = since.map(|t| clock.format(t));
+                let extra_args = match &since {
+                    Some(since) => vec!["-T".to_string(), since.clone()],
+                    None => Vec::new(),
+                };
+                let first = self.spawn(&extra_args).await?;
+                Ok(supervise(self.clone(), clock.clone(), since, first))
+            }
+            Source::File { path, binary } => {
+                let file = tokio::fs::File::open(path).await?;
+                Ok(match binary {
+                    true => read_entries(file),
+                    false => read_lines(file),
+                })
+            }
+            Source::Stdin { binary: true } => Ok(read_entries(tokio::io::stdin())),
+            Source::Stdin { binary: false } => Ok(read_lines(tokio::io::stdin())),
+        }
+    }
+}
+
+// Keeps a live source running across disconnects and adb server restarts.
+// Every `Exit` is followed by waiting for the device and restarting logcat with
+// `-T` at the last seen timestamp, so the stream only ends when the program does.
+fn supervise(
+    mut source: Source,
+    clock: DeviceClock,
+    mut since: Option<String>,
+    first: SourceStream,
+) -> SourceStream {
+    let mut parser = LogcatParser::default();
+    parser.clock = clock.clone();
+    stream! {
+        let mut current = first;
+        // Entries already printed at the last timestamp, `-T` repeats them
+        let mut seen: Vec<String> = Vec::new();
+        loop {
+            let mut resyncing = !seen.is_empty();
+            // Output of a logcat that may reject `-D`, until an entry shows it didn't
+            let mut held = source.has_dividers().then(Vec::new);
+            let mut rejected = false;
+            while let Some(item) = current.next().await {
+                let entry = match &item {
+                    SourceItem::Output(line) => parser
+                        .try_parse(line)
+                        .and_then(|r| r.timestamp)
+                        .map(|t| (t, line.clone())),
+                    SourceItem::Record(record) => record.timestamp.map(|t| {
+                        let key = format!("{} {} {} {}", record.pid, record.tid, record.tag, record.message);
+                        (t, key)
+                    }),
+                    _ => None,
+                };
+                if let Some(lines) = &mut held {
+                    match (&item, &entry) {
+                        (SourceItem::Output(_) | SourceItem::Flush, None) => {
+                            lines.push(item);
+                            continue;
+                        }
+                        (SourceItem::Exit(_), _) => {
+                            rejected = lines.iter().any(|l| {
+                                matches!(l, SourceItem::Output(line) if line.contains("option") || line.contains("Usage"))
+                            });
+                            if rejected && source.drop_dividers() {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    for line in held.take().into_iter().flatten() {
+                        yield line;
+                    }
+                }
+                if let Some((timestamp, key)) = entry {
+                    if resyncing && seen.contains(&key) {
+                        continue;
+                    }
+                    resyncing = false;
+                    let timestamp = Some(clock.format(timestamp));
+                    if timestamp != since {
+                        seen.clear();
+                        since = timestamp;
+                    }
+                    seen.push(key);
+                }
+                yield item;
+            }
+            if rejected {
+                yield SourceItem::Error("logcat doesn't know -D, buffers of interleaved entries are unknown".to_string());
+            } else {
+                tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
+                if let Some(client) = source.client() {
+                    if let Err(err) = client.wait_for_device().await {
+                        yield SourceItem::Error(err.to_string());
+                        continue;
+                    }
+                }
+            }
+            let extra_args = match &since {
+                Some(since) => vec!["-T".to_string(), since.clone()],
+                None => Vec::new(),
+            };
+            current = match source.spawn(&extra_args).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    yield SourceItem::Error(err.to_string());
+                    continue;
+                }
+            };
+        }
+    }
+    .boxed()
+}
+
+// Saved logs are not always valid UTF-8 and may have CRLF line endings,
+// so read raw lines instead of `AsyncBufReadExt::lines`.
+fn read_lines<R: AsyncRead + Send + Unpin + 'static>(reader: R) -> SourceStream {
+    let mut reader = BufReader::new(reader);
+    stream! {
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf).await {
+                Ok(0) => break,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buf);
+                    yield SourceItem::Output(line.trim_end_matches(['\r', '\n']).to_string());
+                }
+                Err(err) => {
+                    yield SourceItem::Error(err.to_string());
+                    break;
+                }
+            }
+        }
+        // A `long` log may not end with a blank line
+        yield SourceItem::Flush;
+    }
+    .boxed()
+}
+
+// `logcat -B` output, one `logger_entry` at a time
+fn read_entries<R: AsyncRead + Send + Unpin + 'static>(reader: R) -> SourceStream {
+    let mut reader = BufReader::new(reader);
+    stream! {
+        let mut entry = vec![0; 4];
+        loop {
+            entry.resize(4, 0);
+            match reader.read_exact(&mut entry).await {
+                Ok(_) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(err) => {
+                    yield SourceItem::Error(err.to_string());
+                    break;
+                }
+            }
+            let (hdr_size, len) = binary::entry_size(&entry);
+            // Text on a binary stream would be read as garbage sizes, stop instead
+            if !binary::HEADER_SIZES.contains(&hdr_size) {
+                yield SourceItem::Error(format!("not a logcat -B stream, header size {}", hdr_size));
+                break;
+            }
+            entry.resize(hdr_size + len, 0);
+            if let Err(err) = reader.read_exact(&mut entry[4..]).await {
+                yield SourceItem::Error(err.to_string());
+                break;
+            }
+            match binary::parse_entry(&entry) {
+                Some(record) => yield SourceItem::Record(Box::new(record)),
+                None => yield SourceItem::Error("malformed logger_entry".to_string()),
+            }
+        }
+    }
+    .boxed()
+}
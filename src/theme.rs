@@ -0,0 +1,228 @@
+use crate::filter::wildcard_match;
+use crate::record::Level;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use termcolor::Color;
+
+pub const BUILTIN_THEMES: &[&str] = &["dark", "light", "solarized"];
+
+// Colors of the terminal output, tags without an explicit color get a hashed one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub timestamp: Option<Color>,
+    // Background of the level badge and color of the message
+    pub verbose: Option<Color>,
+    pub debug: Option<Color>,
+    pub info: Option<Color>,
+    pub warn: Option<Color>,
+    // Also used for fatal and assert
+    pub error: Option<Color>,
+    // Text of the level badge
+    pub badge: Color,
+    // Tag of decoded `events` entries
+    pub events: Color,
+    // Checked in order, tags may contain `*` wildcards
+    pub tags: Vec<(String, Color)>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            timestamp: None,
+            verbose: None,
+            debug: None,
+            info: Some(Color::Green),
+            warn: Some(Color::Yellow),
+            error: Some(Color::Red),
+            badge: Color::Black,
+            events: Color::Magenta,
+            tags: Vec::new(),
+        }
+    }
+}
+
+// A `[themes.<name>]` section of the config, colors are termcolor names,
+// ANSI 256 numbers or `r,g,b` triples
+//
+//   [themes.mine]
+//   base = "solarized"
+//   warn = "208"
+//   tags = { ActivityManager = "cyan", "Camera*" = "38,139,210" }
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeSettings {
+    // Built-in or configured theme the others are changes to, `dark` if unset
+    pub base: Option<String>,
+    pub timestamp: Option<String>,
+    pub verbose: Option<String>,
+    pub debug: Option<String>,
+    pub info: Option<String>,
+    pub warn: Option<String>,
+    pub error: Option<String>,
+    pub badge: Option<String>,
+    pub events: Option<String>,
+    pub tags: BTreeMap<String, String>,
+}
+
+fn parse_color(name: &str) -> Result<Color> {
+    Color::from_str(name).map_err(|err| anyhow!("invalid color '{}': {}", name, err))
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Theme::default()),
+            // Yellow text is unreadable on white
+            "light" => Some(Theme {
+                timestamp: Some(Color::Ansi256(242)),
+                warn: Some(Color::Ansi256(130)),
+                error: Some(Color::Ansi256(160)),
+                info: Some(Color::Ansi256(28)),
+                badge: Color::White,
+                events: Color::Ansi256(90),
+                ..Theme::default()
+            }),
+            // https://ethanschoonover.com/solarized
+            "solarized" => Some(Theme {
+                timestamp: Some(Color::Rgb(0x58, 0x6e, 0x75)),
+                verbose: Some(Color::Rgb(0x65, 0x7b, 0x83)),
+                debug: Some(Color::Rgb(0x26, 0x8b, 0xd2)),
+                info: Some(Color::Rgb(0x85, 0x99, 0x00)),
+                warn: Some(Color::Rgb(0xb5, 0x89, 0x00)),
+                error: Some(Color::Rgb(0xdc, 0x32, 0x2f)),
+                badge: Color::Rgb(0x00, 0x2b, 0x36),
+                events: Color::Rgb(0xd3, 0x36, 0x82),
+                tags: Vec::new(),
+            }),
+            _ => None,
+        }
+    }
+    // A configured theme replaces the built-in one of the same name
+    pub fn named(name: &str, themes: &BTreeMap<String, ThemeSettings>) -> Result<Self> {
+        Self::resolve(name, themes, 0)
+    }
+    fn resolve(name: &str, themes: &BTreeMap<String, ThemeSettings>, depth: usize) -> Result<Self> {
+        if depth > themes.len() {
+            return Err(anyhow!("theme '{}' is based on itself", name));
+        }
+        let Some(settings) = themes.get(name) else {
+            return Self::builtin(name).ok_or_else(|| {
+                anyhow!(
+                    "unknown theme '{}', available: {}",
+                    name,
+                    BUILTIN_THEMES
+                        .iter()
+                        .copied()
+                        .chain(themes.keys().map(String::as_str))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            });
+        };
+        let mut theme = match settings.base.as_deref() {
+            // Based on the built-in theme it overrides
+            Some(base) if base == name => {
+                Self::builtin(base).ok_or_else(|| anyhow!("theme '{}' is based on itself", name))?
+            }
+            Some(base) => Self::resolve(base, themes, depth + 1)?,
+            None => Theme::default(),
+        };
+        let levels = [
+            (&settings.timestamp, &mut theme.timestamp),
+            (&settings.verbose, &mut theme.verbose),
+            (&settings.debug, &mut theme.debug),
+            (&settings.info, &mut theme.info),
+            (&settings.warn, &mut theme.warn),
+            (&settings.error, &mut theme.error),
+        ];
+        for (setting, color) in levels {
+            if let Some(name) = setting {
+                *color = Some(parse_color(name)?);
+            }
+        }
+        if let Some(badge) = &settings.badge {
+            theme.badge = parse_color(badge)?;
+        }
+        if let Some(events) = &settings.events {
+            theme.events = parse_color(events)?;
+        }
+        // Exact tags win over wildcards, and this theme's over its base's
+        let mut tags = settings
+            .tags
+            .iter()
+            .map(|(tag, color)| Ok((tag.clone(), parse_color(color)?)))
+            .collect::<Result<Vec<_>>>()?;
+        tags.sort_by_key(|(tag, _)| tag.contains('*'));
+        tags.append(&mut theme.tags);
+        theme.tags = tags;
+        Ok(theme)
+    }
+    pub fn level_color(&self, level: Level) -> Option<Color> {
+        match level {
+            Level::Verbose => self.verbose,
+            Level::Debug => self.debug,
+            Level::Info => self.info,
+            Level::Warn => self.warn,
+            Level::Error | Level::Fatal | Level::Assert => self.error,
+            _ => None,
+        }
+    }
+    pub fn tag_color(&self, tag: &str) -> Option<Color> {
+        self.tags
+            .iter()
+            .find(|(pattern, _)| {
+                if pattern.contains('*') {
+                    wildcard_match(pattern, tag)
+                } else {
+                    pattern == tag
+                }
+            })
+            .map(|(_, color)| *color)
+    }
+}
+
+#[test]
+fn configured_theme() {
+    let themes: BTreeMap<String, ThemeSettings> = toml::from_str(
+        r#"
+        [mine]
+        base = "solarized"
+        warn = "208"
+        tags = { ActivityManager = "cyan", "Activity*" = "1,2,3", "Camera*" = "red" }
+
+        [light]
+        base = "light"
+        info = "blue"
+
+        [loop]
+        base = "loop2"
+        [loop2]
+        base = "loop"
+        "#,
+    )
+    .unwrap();
+    let theme = Theme::named("mine", &themes).unwrap();
+    assert_eq!(theme.warn, Some(Color::Ansi256(208)));
+    assert_eq!(theme.error, Theme::builtin("solarized").unwrap().error);
+    assert_eq!(theme.tag_color("ActivityManager"), Some(Color::Cyan));
+    assert_eq!(
+        theme.tag_color("ActivityTaskManager"),
+        Some(Color::Rgb(1, 2, 3))
+    );
+    assert_eq!(theme.tag_color("CameraService"), Some(Color::Red));
+    assert_eq!(theme.tag_color("Zygote"), None);
+    assert_eq!(theme.level_color(Level::Fatal), theme.error);
+
+    let light = Theme::named("light", &themes).unwrap();
+    assert_eq!(light.info, Some(Color::Blue));
+    assert_eq!(light.badge, Color::White);
+    assert_eq!(Theme::named("dark", &themes).unwrap(), Theme::default());
+
+    assert!(Theme::named("loop", &themes).is_err());
+    assert!(Theme::named("neon", &themes).is_err());
+    let bad: BTreeMap<String, ThemeSettings> =
+        toml::from_str("[bad]\ninfo = \"greenish\"").unwrap();
+    assert!(Theme::named("bad", &bad).is_err());
+}
@@ -0,0 +1,726 @@
+use crate::filter::Filter;
+use crate::record::{LogBuffer, LogcatRecord};
+use crate::utils::{hashed_color, message_text, Preamble, Terminal};
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::Paragraph;
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use tokio::sync::mpsc;
+
+// Entries kept for scrolling back unless `--history` says otherwise
+pub const DEFAULT_HISTORY: usize = 10_000;
+
+enum Entry {
+    Record(Box<LogcatRecord>),
+    Separator(String),
+    Unparsed(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Prompt {
+    Filter,
+    Search,
+}
+
+// Everything on screen except the terminal itself
+pub struct View {
+    // Column widths and colors of the streaming output
+    layout: Terminal,
+    history: VecDeque<Entry>,
+    capacity: usize,
+    // Entries dropped from the front so far, positions are counted from the first ever
+    dropped: usize,
+    filter: Filter,
+    filter_text: String,
+    search: String,
+    // What is being edited, its text and the value to restore on Esc
+    prompt: Option<(Prompt, String, String)>,
+    // First entry shown while paused, None follows new entries
+    top: Option<usize>,
+    // Entries shown by the last draw
+    shown: (usize, usize),
+    height: usize,
+    error: Option<String>,
+    // Shown on the status line until the next key, stderr would scribble over the screen
+    warning: Option<String>,
+    dirty: bool,
+}
+
+// The full screen alternative to `Terminal`, keeps a bounded history to
+// scroll back through while records keep coming
+pub struct Tui {
+    terminal: DefaultTerminal,
+    pub view: View,
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+impl Tui {
+    pub fn new(view: View) -> Result<Self> {
+        Ok(Tui {
+            terminal: ratatui::try_init()?,
+            view,
+        })
+    }
+    // Only redraws when something changed since the last call
+    pub fn draw(&mut self) -> Result<()> {
+        if std::mem::take(&mut self.view.dirty) {
+            self.terminal.draw(|frame| self.view.render(frame))?;
+        }
+        Ok(())
+    }
+}
+
+// Terminal events read on a thread of their own, crossterm only has a blocking API
+pub fn input_events() -> mpsc::UnboundedReceiver<Event> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn tui_color(color: termcolor::Color) -> Color {
+    match color {
+        termcolor::Color::Black => Color::Black,
+        termcolor::Color::Blue => Color::Blue,
+        termcolor::Color::Green => Color::Green,
+        termcolor::Color::Red => Color::Red,
+        termcolor::Color::Cyan => Color::Cyan,
+        termcolor::Color::Magenta => Color::Magenta,
+        termcolor::Color::Yellow => Color::Yellow,
+        termcolor::Color::White => Color::Gray,
+        termcolor::Color::Ansi256(c) => Color::Indexed(c),
+        termcolor::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+        _ => Color::Reset,
+    }
+}
+
+// `--bright-colors` for the named colors, like `set_intense`
+fn intense(color: Color) -> Color {
+    match color {
+        Color::Black => Color::DarkGray,
+        Color::Blue => Color::LightBlue,
+        Color::Green => Color::LightGreen,
+        Color::Red => Color::LightRed,
+        Color::Cyan => Color::LightCyan,
+        Color::Magenta => Color::LightMagenta,
+        Color::Yellow => Color::LightYellow,
+        Color::Gray => Color::White,
+        color => color,
+    }
+}
+
+// Lowercase searches ignore case
+fn find_all(text: &str, search: &str) -> Vec<(usize, usize)> {
+    if search.is_empty() {
+        return Vec::new();
+    }
+    let (haystack, needle) = if search.chars().any(char::is_uppercase) {
+        (text.to_string(), search.to_string())
+    } else {
+        (text.to_lowercase(), search.to_string())
+    };
+    // Lowercasing may change byte lengths, only highlight when it didn't
+    if haystack.len() != text.len() {
+        return Vec::new();
+    }
+    haystack
+        .match_indices(&needle)
+        .filter(|(start, _)| text.is_char_boundary(*start))
+        .map(|(start, m)| (start, start + m.len()))
+        .collect()
+}
+
+fn byte_offset(text: &str, chars: usize) -> usize {
+    text.char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+// Search matches are reversed, `--grep` matches (character ranges) underlined
+// like on the terminal, the two may overlap
+fn highlighted(
+    text: &str,
+    search: &str,
+    grep: &[(usize, usize)],
+    style: Style,
+) -> Vec<Span<'static>> {
+    let search = find_all(text, search);
+    let grep = grep
+        .iter()
+        .map(|&(start, end)| (byte_offset(text, start), byte_offset(text, end)))
+        .collect::<Vec<_>>();
+    let mut bounds = vec![0, text.len()];
+    for &(start, end) in search.iter().chain(&grep) {
+        bounds.extend([start, end]);
+    }
+    bounds.sort_unstable();
+    bounds.dedup();
+    bounds
+        .windows(2)
+        .map(|w| {
+            let inside = |ranges: &[(usize, usize)]| {
+                ranges
+                    .iter()
+                    .any(|&(start, end)| start <= w[0] && w[1] <= end)
+            };
+            let mut style = style;
+            if inside(&grep) {
+                style = style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+            }
+            if inside(&search) {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            Span::styled(text[w[0]..w[1]].to_string(), style)
+        })
+        .collect()
+}
+
+impl View {
+    pub fn new(layout: Terminal, filter: &[String], capacity: usize) -> Result<Self> {
+        Ok(View {
+            layout,
+            history: VecDeque::new(),
+            capacity: capacity.max(1),
+            dropped: 0,
+            filter: Filter::parse(filter)?,
+            filter_text: filter.join(" "),
+            search: String::new(),
+            prompt: None,
+            top: None,
+            shown: (0, 0),
+            height: 0,
+            error: None,
+            warning: None,
+            dirty: true,
+        })
+    }
+    fn push(&mut self, entry: Entry) {
+        if self.history.len() == self.capacity {
+            self.history.pop_front();
+            self.dropped += 1;
+        }
+        self.history.push_back(entry);
+        self.dirty = true;
+    }
+    pub fn push_record(&mut self, record: &LogcatRecord) {
+        self.push(Entry::Record(Box::new(record.clone())));
+    }
+    pub fn push_separator(&mut self, device: Option<&str>, text: &str) {
+        self.push(Entry::Separator(match device {
+            Some(device) => format!("{}: {}", device, text),
+            None => text.to_string(),
+        }));
+    }
+    pub fn push_unparsed(&mut self, device: Option<&str>, line: &str) {
+        self.push(Entry::Unparsed(match device {
+            Some(device) => format!(
+                "{:<width$} {}",
+                device,
+                line,
+                width = self.layout.device_width
+            ),
+            None => line.to_string(),
+        }));
+    }
+    pub fn warn(&mut self, device: Option<&str>, text: &str) {
+        self.warning = Some(match device {
+            Some(device) => format!("{}: {}", device, text),
+            None => text.to_string(),
+        });
+        self.dirty = true;
+    }
+    // Unparsed lines can't be matched against tags so any filter hides them
+    fn is_visible(&self, entry: &Entry) -> bool {
+        match entry {
+            Entry::Record(record) => self.filter.matches(record),
+            Entry::Separator(_) => true,
+            Entry::Unparsed(_) => self.filter.is_empty(),
+        }
+    }
+    fn matches_search(&self, entry: &Entry) -> bool {
+        let text = match entry {
+            Entry::Record(record) => format!("{} {}", record.tag, message_text(record)),
+            Entry::Separator(text) | Entry::Unparsed(text) => text.clone(),
+        };
+        !find_all(&text, &self.search).is_empty()
+    }
+    // Positions of the shown entries, oldest first
+    fn visible(&self) -> Vec<usize> {
+        self.history
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| self.is_visible(entry))
+            .map(|(i, _)| self.dropped + i)
+            .collect()
+    }
+    fn entry(&self, position: usize) -> &Entry {
+        &self.history[position - self.dropped]
+    }
+    // Moves the first shown entry by `by` visible entries and pauses
+    fn scroll(&mut self, by: isize) {
+        let visible = self.visible();
+        let top = self.top.unwrap_or(self.shown.0);
+        let index = visible.partition_point(|&p| p < top) as isize;
+        let index = (index + by).clamp(0, visible.len().saturating_sub(1) as isize) as usize;
+        self.top = visible.get(index).copied().or(Some(top));
+    }
+    // Next or previous entry matching the search, scrolled to the top
+    fn find(&mut self, forward: bool) {
+        let from = self.top.unwrap_or(self.shown.0);
+        let visible = self.visible();
+        let found = if forward {
+            visible
+                .iter()
+                .find(|&&p| p > from && self.matches_search(self.entry(p)))
+        } else {
+            visible
+                .iter()
+                .rev()
+                .find(|&&p| p < from && self.matches_search(self.entry(p)))
+        };
+        match found {
+            Some(&position) => self.top = Some(position),
+            None => self.error = Some(format!("'{}' not found", self.search)),
+        }
+    }
+    fn apply_prompt(&mut self, prompt: Prompt, text: &str) {
+        match prompt {
+            Prompt::Filter => match Filter::parse(&[text]) {
+                Ok(filter) => {
+                    self.filter = filter;
+                    self.filter_text = text.to_string();
+                    self.error = None;
+                }
+                Err(err) => self.error = Some(err.to_string()),
+            },
+            Prompt::Search => {
+                self.search = text.to_string();
+                self.error = None;
+            }
+        }
+    }
+    // False once the user asked to quit
+    pub fn handle(&mut self, event: Event) -> bool {
+        self.dirty = true;
+        let Event::Key(key) = event else {
+            return true;
+        };
+        if key.kind != KeyEventKind::Press {
+            return true;
+        }
+        self.warning = None;
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+        if self.prompt.is_some() {
+            self.handle_prompt(key);
+            return true;
+        }
+        let page = self.height.max(2) as isize - 1;
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Char(' ') | KeyCode::Char('p') => {
+                self.top = match self.top {
+                    Some(_) => None,
+                    None => Some(self.shown.0),
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.scroll(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll(1),
+            KeyCode::PageUp => self.scroll(-page),
+            KeyCode::PageDown => self.scroll(page),
+            KeyCode::Home | KeyCode::Char('g') => {
+                self.top = Some(self.visible().first().copied().unwrap_or(self.dropped))
+            }
+            KeyCode::End | KeyCode::Char('G') => self.top = None,
+            KeyCode::Char('f') => {
+                let text = self.filter_text.clone();
+                self.prompt = Some((Prompt::Filter, text.clone(), text));
+            }
+            KeyCode::Char('/') => {
+                let text = self.search.clone();
+                self.prompt = Some((Prompt::Search, text.clone(), text));
+            }
+            KeyCode::Char('n') => self.find(true),
+            KeyCode::Char('N') => self.find(false),
+            _ => {}
+        }
+        true
+    }
+    // Edits apply as they are typed, Esc goes back to what was there before
+    fn handle_prompt(&mut self, key: KeyEvent) {
+        let Some((prompt, mut text, original)) = self.prompt.take() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Enter => {
+                if prompt == Prompt::Search && !text.is_empty() {
+                    self.find(true);
+                }
+                return;
+            }
+            KeyCode::Esc => {
+                self.apply_prompt(prompt, &original);
+                return;
+            }
+            KeyCode::Backspace => {
+                text.pop();
+            }
+            KeyCode::Char(c) => text.push(c),
+            _ => {}
+        }
+        self.apply_prompt(prompt, &text);
+        self.prompt = Some((prompt, text, original));
+    }
+    fn record_lines(&self, record: &LogcatRecord) -> Vec<Line<'static>> {
+        let layout = &self.layout;
+        let preamble = layout.preamble(record);
+        let preamble_width = preamble.width();
+        let Preamble {
+            device,
+            buffer_name,
+            datetime,
+            tag,
+            process_name,
+            thread,
+        } = preamble;
+        let is_crash = record.buffer == Some(LogBuffer::Crash);
+        let level_color = layout.theme.level_color(record.level).map(tui_color);
+        let mut spans = Vec::new();
+        if !device.is_empty() {
+            let style = Style::new()
+                .fg(tui_color(hashed_color(&device)))
+                .add_modifier(Modifier::BOLD);
+            spans.push(Span::styled(device, style));
+        }
+        if !buffer_name.is_empty() {
+            let style = match is_crash {
+                true => Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+                false => Style::new().fg(tui_color(hashed_color(&buffer_name))),
+            };
+            spans.push(Span::styled(buffer_name, style));
+        }
+        let timestamp = match layout.theme.timestamp {
+            Some(color) => Style::new().fg(tui_color(color)),
+            None => Style::new(),
+        };
+        spans.push(Span::styled(datetime, timestamp));
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(
+            tag,
+            Style::new().fg(tui_color(layout.tag_color(record))),
+        ));
+        spans.push(Span::raw(" ["));
+        let pid_color = tui_color(hashed_color(&process_name));
+        spans.push(Span::styled(process_name, Style::new().fg(pid_color)));
+        spans.push(Span::raw("] "));
+        if !thread.is_empty() {
+            let style = Style::new()
+                .fg(tui_color(hashed_color(&thread)))
+                .add_modifier(Modifier::DIM);
+            spans.push(Span::styled(thread, style));
+        }
+        let badge = match level_color {
+            Some(color) => Style::new().bg(color).fg(tui_color(layout.theme.badge)),
+            None => Style::new(),
+        };
+        spans.push(Span::styled(format!(" {} ", record.level), badge));
+
+        let mut message = Style::new();
+        if let Some(color) = level_color {
+            message = message.fg(match layout.bright_colors {
+                true => intense(color),
+                false => color,
+            });
+        }
+        if is_crash {
+            message = message.add_modifier(Modifier::BOLD);
+        }
+        let text = message_text(record).replace('\t', "");
+        let grep = layout.grep.highlights(&text);
+        let message_lines = text.split('\n').collect::<Vec<_>>();
+        let mut lines = Vec::new();
+        // Character offset of the current line in the message
+        let mut offset = 0;
+        for (i, line) in message_lines.iter().enumerate() {
+            let glyph = if message_lines.len() == 1 {
+                "   "
+            } else if i == 0 {
+                " ┌ "
+            } else if i == message_lines.len() - 1 {
+                " └ "
+            } else {
+                " ├ "
+            };
+            if i > 0 {
+                spans.push(Span::raw(" ".repeat(preamble_width)));
+            }
+            spans.push(Span::raw(glyph));
+            let len = line.chars().count();
+            let line_grep = grep
+                .iter()
+                .map(|&(start, end)| {
+                    let clip = |at: usize| at.saturating_sub(offset).min(len);
+                    (clip(start), clip(end))
+                })
+                .filter(|(start, end)| start < end)
+                .collect::<Vec<_>>();
+            offset += len + 1;
+            spans.extend(highlighted(line, &self.search, &line_grep, message));
+            lines.push(Line::from(std::mem::take(&mut spans)));
+        }
+        lines
+    }
+    fn lines(&self, entry: &Entry) -> Vec<Line<'static>> {
+        let dimmed = Style::new().add_modifier(Modifier::DIM);
+        match entry {
+            Entry::Record(record) => self.record_lines(record),
+            Entry::Separator(text) => vec![Line::from(Span::styled(
+                format!("──── {} ────", text),
+                dimmed,
+            ))],
+            Entry::Unparsed(text) => {
+                vec![Line::from(highlighted(text, &self.search, &[], dimmed))]
+            }
+        }
+    }
+    fn status(&self, visible: &[usize]) -> Line<'static> {
+        let inverted = Style::new().add_modifier(Modifier::REVERSED);
+        if let Some((prompt, text, _)) = &self.prompt {
+            let label = match prompt {
+                Prompt::Filter => "filter",
+                Prompt::Search => "search",
+            };
+            let mut spans = vec![Span::styled(format!(" {}: ", label), inverted)];
+            spans.push(Span::raw(format!(" {}█", text)));
+            if let Some(error) = &self.error {
+                spans.push(Span::styled(
+                    format!("  {}", error),
+                    Style::new().fg(Color::Red),
+                ));
+            }
+            return Line::from(spans);
+        }
+        let state = match self.top {
+            Some(_) => {
+                let below = visible.iter().filter(|&&p| p > self.shown.1).count();
+                format!(" PAUSED +{} ", below)
+            }
+            None => " FOLLOWING ".to_string(),
+        };
+        let mut spans = vec![Span::styled(state, inverted)];
+        spans.push(Span::raw(format!(
+            " {}/{} ",
+            visible.len(),
+            self.history.len()
+        )));
+        if !self.filter_text.is_empty() {
+            spans.push(Span::raw(format!(" filter: {} ", self.filter_text)));
+        }
+        if !self.search.is_empty() {
+            spans.push(Span::raw(format!(" search: {} ", self.search)));
+        }
+        match (&self.error, &self.warning) {
+            (Some(error), _) => spans.push(Span::styled(
+                format!(" {}", error),
+                Style::new().fg(Color::Red),
+            )),
+            (None, Some(warning)) => spans.push(Span::styled(
+                format!(" {}", warning),
+                Style::new().fg(Color::Yellow),
+            )),
+            (None, None) => spans.push(Span::styled(
+                " space pause  ↑↓ scroll  G bottom  f filter  / search  n/N next  q quit",
+                Style::new().add_modifier(Modifier::DIM),
+            )),
+        }
+        Line::from(spans)
+    }
+    pub fn render(&mut self, frame: &mut Frame) {
+        let [body, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        self.height = body.height as usize;
+        let visible = self.visible();
+        // Filled downwards from the paused entry, or upwards from the newest one
+        let mut lines = Vec::new();
+        let (first, last) = match self.top.map(|top| visible.partition_point(|&p| p < top)) {
+            Some(start) => {
+                let mut end = start;
+                for &position in &visible[start..] {
+                    if lines.len() >= self.height {
+                        break;
+                    }
+                    lines.extend(self.lines(self.entry(position)));
+                    end = position;
+                }
+                lines.truncate(self.height);
+                (visible.get(start).copied().unwrap_or(self.dropped), end)
+            }
+            None => {
+                let mut start = visible.len();
+                while start > 0 && lines.len() < self.height {
+                    start -= 1;
+                    let mut entry = self.lines(self.entry(visible[start]));
+                    entry.append(&mut lines);
+                    lines = entry;
+                }
+                let skip = lines.len().saturating_sub(self.height);
+                lines.drain(..skip);
+                (
+                    visible.get(start).copied().unwrap_or(self.dropped),
+                    visible.last().copied().unwrap_or(self.dropped),
+                )
+            }
+        };
+        self.shown = (first, last);
+        frame.render_widget(Paragraph::new(lines), body);
+        frame.render_widget(Paragraph::new(self.status(&visible)), status);
+    }
+}
+
+#[cfg(test)]
+fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+#[test]
+fn tui_history_and_filter() {
+    use crate::record::Level;
+    use ratatui::backend::TestBackend;
+
+    let mut layout = Terminal::default();
+    layout.hide_timestamp = true;
+    layout.tag_width = 8;
+    layout.process_name_width = 6;
+    let mut view = View::new(layout, &[], 3).unwrap();
+    for (i, tag) in ["Zygote", "Camera", "Zygote", "Camera"].iter().enumerate() {
+        view.push_record(&LogcatRecord {
+            level: Level::Info,
+            tag: tag.to_string(),
+            process_name: "app".to_string(),
+            message: format!("line {}", i),
+            ..LogcatRecord::default()
+        });
+    }
+    let mut terminal = ratatui::Terminal::new(TestBackend::new(40, 3)).unwrap();
+    let screen = |terminal: &ratatui::Terminal<TestBackend>| {
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+    };
+    terminal.draw(|frame| view.render(frame)).unwrap();
+    // Only the last 3 are kept, the newest at the bottom
+    assert_eq!(
+        screen(&terminal)[..2],
+        [
+            " Zygote   [   app]  I    line 2",
+            " Camera   [   app]  I    line 3"
+        ]
+    );
+    assert!(screen(&terminal)[2].starts_with(" FOLLOWING  3/3"));
+
+    // Scrolling up pauses on the previous entry
+    assert!(view.handle(key(KeyCode::Up)));
+    assert!(view.top.is_some());
+    terminal.draw(|frame| view.render(frame)).unwrap();
+    assert_eq!(screen(&terminal)[0], " Camera   [   app]  I    line 1");
+
+    // Filters are edited live
+    view.handle(key(KeyCode::Char('f')));
+    for c in "Camera:I *:S".chars() {
+        view.handle(key(KeyCode::Char(c)));
+    }
+    view.handle(key(KeyCode::Enter));
+    view.handle(key(KeyCode::End));
+    terminal.draw(|frame| view.render(frame)).unwrap();
+    assert_eq!(
+        screen(&terminal)[..2],
+        [
+            " Camera   [   app]  I    line 1",
+            " Camera   [   app]  I    line 3"
+        ]
+    );
+    assert!(screen(&terminal)[2].starts_with(" FOLLOWING  2/3  filter: Camera:I *:S"));
+
+    // Esc restores the filter from before the edit
+    view.handle(key(KeyCode::Char('f')));
+    view.handle(key(KeyCode::Backspace));
+    view.handle(key(KeyCode::Esc));
+    assert_eq!(view.filter_text, "Camera:I *:S");
+
+    view.handle(key(KeyCode::Char('/')));
+    view.handle(key(KeyCode::Char('3')));
+    view.handle(key(KeyCode::Enter));
+    assert_eq!(view.top, Some(3));
+    assert!(!view.handle(key(KeyCode::Char('q'))));
+}
+
+#[test]
+fn tui_grep_and_warnings() {
+    use crate::filter::{Grep, GrepOptions};
+    use ratatui::backend::TestBackend;
+
+    let mut layout = Terminal::default();
+    layout.hide_timestamp = true;
+    layout.tag_width = 4;
+    layout.process_name_width = 3;
+    layout.grep = Grep::new(&["fail"], &[], &[], GrepOptions::default()).unwrap();
+    let mut view = View::new(layout, &[], 10).unwrap();
+    view.push_record(&LogcatRecord {
+        tag: "Net".to_string(),
+        process_name: "app".to_string(),
+        message: "ok\nfailed".to_string(),
+        ..LogcatRecord::default()
+    });
+    view.warn(Some("emulator-5554"), "- waiting for device -");
+    let mut terminal = ratatui::Terminal::new(TestBackend::new(60, 3)).unwrap();
+    terminal.draw(|frame| view.render(frame)).unwrap();
+    let buffer = terminal.backend().buffer();
+    let row = |y: u16| {
+        (0..buffer.area.width)
+            .map(|x| buffer[(x, y)].symbol())
+            .collect::<String>()
+    };
+    let underlined = |x: usize, y: u16| {
+        buffer[(x as u16, y)]
+            .modifier
+            .contains(Modifier::UNDERLINED)
+    };
+    // `--grep` matches are underlined on the continuation line only
+    let line = row(1);
+    let at = line[..line.find("failed").unwrap()].chars().count();
+    assert!(underlined(at, 1) && underlined(at + 3, 1));
+    assert!(!underlined(at + 4, 1));
+    assert!((0..buffer.area.width as usize).all(|x| !underlined(x, 0)));
+    assert!(row(2).contains("emulator-5554: - waiting for device -"));
+
+    // Any key dismisses the warning
+    view.handle(key(KeyCode::Char('k')));
+    terminal.draw(|frame| view.render(frame)).unwrap();
+    let buffer = terminal.backend().buffer();
+    let status = (0..buffer.area.width)
+        .map(|x| buffer[(x, 2)].symbol())
+        .collect::<String>();
+    assert!(!status.contains("waiting for device"));
+}
@@ -1,4 +1,4 @@
-use std::{env, io::Write, path::PathBuf, usize::MAX};
+use std::{env, io::Write, path::PathBuf, sync::OnceLock};
 
 use anyhow::{Error, Result};
 // Copyright © 2016 Felix Obenhuber
@@ -20,10 +20,13 @@ use anyhow::{Error, Result};
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
+use chrono::{DateTime, FixedOffset, Local};
 use termcolor::{Buffer, BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
 use which::which_in;
 
-use crate::record::{Level, LogcatRecord};
+use crate::filter::Grep;
+use crate::record::{LogBuffer, LogcatRecord};
+use crate::theme::Theme;
 pub fn adb() -> Result<PathBuf> {
     which_in("adb", env::var_os("PATH"), env::current_dir()?).map_err(Into::into)
 }
@@ -36,8 +39,23 @@ pub fn terminal_width() -> Option<usize> {
             .and_then(|e| e.parse::<usize>().ok()),
     }
 }
+// `--utc` or `--tz`, set once at startup
+static ZONE: OnceLock<FixedOffset> = OnceLock::new();
+
+pub fn set_zone(zone: FixedOffset) {
+    let _ = ZONE.set(zone);
+}
+
+// Timestamps are printed in the host's zone unless another is asked for
+pub fn in_zone(time: DateTime<Local>) -> DateTime<FixedOffset> {
+    match ZONE.get() {
+        Some(zone) => time.with_timezone(zone),
+        None => time.fixed_offset(),
+    }
+}
+
 #[cfg(target_os = "windows")]
-fn hashed_color(i: &str) -> Color {
+pub fn hashed_color(i: &str) -> Color {
     let v = i.bytes().fold(42u8, |c, x| c ^ x) % 7;
     match v {
         0 => Color::Blue,
@@ -51,7 +69,7 @@ fn hashed_color(i: &str) -> Color {
 }
 
 #[cfg(not(target_os = "windows"))]
-fn hashed_color(i: &str) -> Color {
+pub fn hashed_color(i: &str) -> Color {
     // Some colors are hard to read on (at least) dark terminals
     // and I consider some others as ugly.
     Color::Ansi256(match i.bytes().fold(42u8, |c, x| c ^ x) {
@@ -65,8 +83,51 @@ fn hashed_color(i: &str) -> Color {
     })
 }
 
+fn char_slice(s: &str, start: usize, end: usize) -> String {
+    s.chars()
+        .skip(start)
+        .take(end.saturating_sub(start))
+        .collect()
+}
+
+// Decoded `events` entries print their fields instead of the raw payload
+pub fn message_text(record: &LogcatRecord) -> String {
+    if record.fields.is_empty() {
+        record.message.clone()
+    } else {
+        record
+            .fields
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+pub struct Preamble {
+    pub device: String,
+    pub buffer_name: String,
+    pub datetime: String,
+    pub tag: String,
+    pub process_name: String,
+    pub thread: String,
+}
+impl Preamble {
+    pub fn width(&self) -> usize {
+        self.device.chars().count()
+            + self.buffer_name.chars().count()
+            + self.datetime.chars().count()
+            + 1 //Space
+            + self.tag.chars().count()
+            + 2 // " ["
+            + self.process_name.chars().count()
+            + 2 // "] "
+            + self.thread.chars().count()
+            + 3 //" D "
+    }
+}
+
 pub struct Terminal {
-    pub width: usize,
     pub buffer: BufferWriter,
     pub tag_width: usize,
     pub process_name_width: usize,
@@ -75,24 +136,36 @@ pub struct Terminal {
     pub use_process_name: bool,
     pub bright_colors: bool,
     pub pid_width: usize,
+    pub device_width: usize,
+    pub show_buffer: bool,
+    pub show_thread: bool,
+    pub thread_width: usize,
+    pub theme: Theme,
+    // Highlights `--grep` matches in messages
+    pub grep: Grep,
 }
 impl Default for Terminal {
     fn default() -> Self {
-        let width = terminal_width().unwrap_or(80);
         let buffer = BufferWriter::stdout(ColorChoice::Auto);
         let tag_width = 30;
         let process_name_width = 20;
         let pid_width = 10;
         Self {
-            width,
             buffer,
             tag_width,
             pid_width,
+            device_width: 0,
+            show_buffer: false,
+            show_thread: false,
+            // Thread names are at most 15 characters
+            thread_width: 15,
             process_name_width,
             hide_timestamp: false,
             hide_date: true,
             use_process_name: true,
-            bright_colors: true,
+            bright_colors: false,
+            theme: Theme::default(),
+            grep: Grep::default(),
         }
     }
 }
@@ -103,19 +176,53 @@ impl Drop for Terminal {
     }
 }
 impl Terminal {
-    pub fn print(&mut self, record: &LogcatRecord) -> Result<()> {
+    // A dimmed full-width rule marking a gap or section in the stream
+    pub fn print_separator(&mut self, device: Option<&str>, text: &str) -> Result<()> {
+        let text = match device {
+            Some(device) => format!(" {}: {} ", device, text),
+            None => format!(" {} ", text),
+        };
+        let width = terminal_width().unwrap_or(80);
+        let fill = width.saturating_sub(text.chars().count()) / 2;
+        let mut buffer = self.buffer.buffer();
+        buffer.set_color(ColorSpec::new().set_dimmed(true))?;
+        write!(
+            buffer,
+            "{}{}{}",
+            "─".repeat(fill),
+            text,
+            "─".repeat(width.saturating_sub(fill + text.chars().count()))
+        )?;
+        buffer.reset()?;
+        buffer.write_all(b"\n")?;
+        self.buffer.print(&buffer).map_err(Into::into)
+    }
+    // Lines that aren't logcat records, e.g. kernel output or garbage
+    pub fn print_unparsed(&mut self, device: Option<&str>, line: &str) -> Result<()> {
+        let mut buffer = self.buffer.buffer();
+        buffer.set_color(ColorSpec::new().set_dimmed(true))?;
+        if let Some(device) = device {
+            write!(buffer, "{:<width$} ", device, width = self.device_width)?;
+        }
+        buffer.write_all(line.as_bytes())?;
+        buffer.reset()?;
+        buffer.write_all(b"\n")?;
+        self.buffer.print(&buffer).map_err(Into::into)
+    }
+    // The fixed width columns in front of the message
+    pub fn preamble(&self, record: &LogcatRecord) -> Preamble {
         let datetime = {
             if self.hide_timestamp {
                 String::new()
             } else if self.hide_date {
                 record
                     .timestamp
-                    .map(|t| t.format("%H:%M:%S").to_string())
+                    .map(|t| in_zone(t).format("%H:%M:%S").to_string())
                     .unwrap_or(" ".repeat(12))
             } else {
                 record
                     .timestamp
-                    .map(|t| t.format("%m-%d %H:%M:%S").to_string())
+                    .map(|t| in_zone(t).format("%m-%d %H:%M:%S").to_string())
                     .unwrap_or(" ".repeat(17))
             }
         };
@@ -155,28 +262,88 @@ impl Terminal {
         } else {
             format!(
                 "{:>width$}",
-                format!("{}:{}", record.pid, record.pid),
+                format!("{}:{}", record.pid, record.tid),
                 width = self.pid_width
             )
         };
-        let preamble_width = datetime.chars().count()
-            + 1 //Space
-            + tag.chars().count()
-            + 2 // " ["
-            + process_name.chars().count()
-            + 2 // "] "
-            + 3; //" D "
-        let timestamp_color = None;
-        let tag_color = hashed_color(&record.tag);
-        let pid_color = hashed_color(&process_name);
-        let level_color = match record.level {
-            Level::Info => Some(Color::Green),
-            Level::Warn => Some(Color::Yellow),
-            Level::Error | Level::Fatal | Level::Assert => Some(Color::Red),
-            _ => None,
+        let device = record
+            .device
+            .as_ref()
+            .map(|d| format!("{:<width$} ", d, width = self.device_width))
+            .unwrap_or_default();
+        let buffer_name = if self.show_buffer {
+            format!(
+                "{:<8} ",
+                record.buffer.map(|b| b.to_string()).unwrap_or_default()
+            )
+        } else {
+            String::new()
+        };
+        let thread = if self.show_thread {
+            let name = record
+                .thread_name
+                .clone()
+                .unwrap_or_else(|| record.tid.to_string());
+            format!(
+                "{:<width$} ",
+                name.chars().take(self.thread_width).collect::<String>(),
+                width = self.thread_width
+            )
+        } else {
+            String::new()
         };
+        Preamble {
+            device,
+            buffer_name,
+            datetime,
+            tag,
+            process_name,
+            thread,
+        }
+    }
+    // Decoded `events` entries share one color so they stand out from regular tags
+    pub fn tag_color(&self, record: &LogcatRecord) -> Color {
+        match self.theme.tag_color(&record.tag) {
+            Some(color) => color,
+            None if record.fields.is_empty() => hashed_color(&record.tag),
+            None => self.theme.events,
+        }
+    }
+    pub fn print(&mut self, record: &LogcatRecord) -> Result<()> {
+        let preamble = self.preamble(record);
+        let preamble_width = preamble.width();
+        let Preamble {
+            device,
+            buffer_name,
+            datetime,
+            tag,
+            process_name,
+            thread,
+        } = preamble;
+        let is_crash = record.buffer == Some(LogBuffer::Crash);
+        let timestamp_color = self.theme.timestamp;
+        let tag_color = self.tag_color(record);
+        let pid_color = hashed_color(&process_name);
+        let device_color = hashed_color(&device);
+        let level_color = self.theme.level_color(record.level);
+        let badge_color = self.theme.badge;
         let write_preamble = |buffer: &mut Buffer| -> Result<(), Error> {
             let mut spec = ColorSpec::new();
+            if !device.is_empty() {
+                buffer.set_color(spec.set_fg(Some(device_color)).set_bold(true))?;
+                buffer.write_all(device.as_bytes())?;
+                spec.set_bold(false);
+            }
+            if !buffer_name.is_empty() {
+                let color = if is_crash {
+                    Color::Red
+                } else {
+                    hashed_color(&buffer_name)
+                };
+                buffer.set_color(spec.set_fg(Some(color)).set_bold(is_crash))?;
+                buffer.write_all(buffer_name.as_bytes())?;
+                spec.set_bold(false);
+            }
             buffer.set_color(spec.set_fg(timestamp_color))?;
             buffer.write_all(datetime.as_bytes())?;
             buffer.write_all(b" ")?;
@@ -190,30 +357,61 @@ impl Terminal {
             buffer.write_all(process_name.as_bytes())?;
             buffer.set_color(spec.set_fg(None))?;
             buffer.write_all(b"] ")?;
+            if !thread.is_empty() {
+                buffer.set_color(spec.set_fg(Some(hashed_color(&thread))).set_dimmed(true))?;
+                buffer.write_all(thread.as_bytes())?;
+                buffer.set_color(spec.set_fg(None).set_dimmed(false))?;
+            }
 
             buffer.set_color(
                 spec.set_bg(level_color)
-                    .set_fg(level_color.map(|_| Color::Black)), // Set fg only if bg is set
+                    .set_fg(level_color.map(|_| badge_color)), // Set fg only if bg is set
             )?;
             write!(buffer, " {} ", record.level)?;
             buffer.set_color(&ColorSpec::new())?;
 
             Ok(())
         };
-        let payload_len = terminal_width().unwrap_or(MAX) - preamble_width - 3;
-        let message = record.message.replace('\t', "");
-        let message_len = message.chars().count();
-        let chunks = message_len / payload_len + 1;
+        let payload_len = terminal_width()
+            .unwrap_or(usize::MAX)
+            .saturating_sub(preamble_width + 3)
+            .max(1);
+        let message = message_text(record).replace('\t', "");
+        let highlights = self.grep.highlights(&message);
+        // Every message line wrapped to the payload width, with the character
+        // offset it starts at
+        let mut offset = 0;
+        let chunks = message
+            .split('\n')
+            .flat_map(|line| {
+                let chars = line.chars().collect::<Vec<char>>();
+                let start = offset;
+                offset += chars.len() + 1;
+                if chars.is_empty() {
+                    return vec![(start, String::new())];
+                }
+                chars
+                    .chunks(payload_len)
+                    .enumerate()
+                    .map(|(i, chunk)| (start + i * payload_len, chunk.iter().collect::<String>()))
+                    .collect()
+            })
+            .collect::<Vec<(usize, String)>>();
         {
             let mut buffer = self.buffer.buffer();
-            for i in 0..chunks {
-                write_preamble(&mut buffer)?;
+            for (i, (offset, chunk)) in chunks.iter().enumerate() {
+                // Continuation lines only get the glyph, not the whole preamble again
+                if i == 0 {
+                    write_preamble(&mut buffer)?;
+                } else {
+                    buffer.write_all(" ".repeat(preamble_width).as_bytes())?;
+                }
 
-                let c = if chunks == 1 {
+                let c = if chunks.len() == 1 {
                     "   "
                 } else if i == 0 {
                     " ┌ "
-                } else if i == chunks - 1 {
+                } else if i == chunks.len() - 1 {
                     " └ "
                 } else {
                     " ├ "
@@ -221,17 +419,29 @@ impl Terminal {
 
                 buffer.write_all(c.as_bytes())?;
 
-                let chunk = message
-                    .chars()
-                    .skip(i * payload_len)
-                    .take(payload_len)
-                    .collect::<String>();
-                buffer.set_color(
-                    ColorSpec::new()
-                        .set_intense(self.bright_colors)
-                        .set_fg(level_color),
-                )?;
-                buffer.write_all(chunk.as_bytes())?;
+                // Crash buffer entries stand out even without the buffer column
+                let mut spec = ColorSpec::new();
+                spec.set_intense(self.bright_colors)
+                    .set_bold(is_crash)
+                    .set_fg(level_color);
+                // `--grep` matches are underlined, split where chunks wrap
+                let mut at = 0;
+                let len = chunk.chars().count();
+                for &(start, end) in &highlights {
+                    let start = start.saturating_sub(*offset).min(len);
+                    let end = end.saturating_sub(*offset).min(len);
+                    if end <= at {
+                        continue;
+                    }
+                    buffer.set_color(&spec)?;
+                    buffer.write_all(char_slice(chunk, at, start).as_bytes())?;
+                    buffer.set_color(spec.clone().set_bold(true).set_underline(true))?;
+                    buffer.write_all(char_slice(chunk, start, end).as_bytes())?;
+                    at = end;
+                }
+                buffer.set_color(&spec)?;
+                buffer.write_all(char_slice(chunk, at, len).as_bytes())?;
+                buffer.reset()?;
                 buffer.write_all(b"\n")?;
             }
             self.buffer.print(&buffer).map_err(Into::into)